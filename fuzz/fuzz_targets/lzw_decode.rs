@@ -0,0 +1,14 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+// lzw.rs - модуль бінарника, а не бібліотеки, тож підключається за шляхом;
+// звідси потрібне лише декодування
+#[path = "../../src/lzw.rs"]
+#[allow(dead_code)]
+mod lzw;
+
+// Будь-який вхід має дати Ok або Err, але не паніку.
+fuzz_target!(|data: &[u8]| {
+    let _ = lzw::decode_bytes(data);
+});
@@ -3,7 +3,7 @@ use crate::utils::{build_sa, build_t, radix_sort};
 use std::fs::File;
 use std::io::{BufReader, BufWriter, Read, Result, Write};
 
-const BLOCK: usize = 256;
+pub const BLOCK: usize = 256;
 
 fn build_matrix(bytes: &[u8], n: usize) -> Vec<Vec<u8>> {
     let mut matrix: Vec<Vec<u8>> = vec![vec![0; n + 1]; n];
@@ -17,7 +17,6 @@ fn build_matrix(bytes: &[u8], n: usize) -> Vec<Vec<u8>> {
 
     matrix
 }
-use std::time::Instant;
 
 // pub fn encode(file_read: &str, file_write: &str) -> Result<()> {
 //     let mut reader = BufReader::new(File::open(file_read)?);
@@ -60,7 +59,6 @@ pub fn encode(file_read: &str, file_write: &str) -> Result<()> {
     let mut writer = BufWriter::new(File::create(file_write)?);
 
     let mut buf = vec![0; BLOCK];
-    let start = Instant::now();
     loop {
         // ---------------------------------------------------------
         // 1) Збираємо порцію блоків
@@ -118,20 +116,19 @@ pub fn encode(file_read: &str, file_write: &str) -> Result<()> {
             writer.write_all(&[primary])?;
         }
     }
-    println!("time: {:?}", start.elapsed());
 
     Ok(())
 }
 
 use rayon::prelude::*;
 
+#[allow(non_snake_case)]
 pub fn encode_SA(file_read: &str, file_write: &str) -> Result<()> {
     let mut reader = BufReader::new(File::open(file_read)?);
     let mut writer = BufWriter::new(File::create(file_write)?);
 
     let mut block = vec![0; BLOCK];
 
-    let start = Instant::now();
     loop {
         // --- 1. Збираємо порцію блоків ---
         let mut batch: Vec<Vec<u8>> = Vec::with_capacity(BATCH_SIZE);
@@ -160,7 +157,6 @@ pub fn encode_SA(file_read: &str, file_write: &str) -> Result<()> {
             writer.write_all(&[primary])?;
         }
     }
-    println!("time: {:?}", start.elapsed());
 
     Ok(())
 }
@@ -207,13 +203,11 @@ pub fn decode(file_read: &str, file_write: &str) -> Result<()> {
     let mut buf = vec![0; BLOCK + 1];
     let mut res: Vec<u8> = vec![0; BLOCK];
 
-    let mut i = 0;
     while let Ok(n) = reader.read(&mut buf) {
         if n == 0 {
             break;
         }
         let size = n - 1;
-        // println!("{i}: size: {size}");
         let t = build_t(&buf[0..size]);
         let mut pos = buf[size] as usize;
         res.iter_mut().for_each(|r| {
@@ -221,7 +215,6 @@ pub fn decode(file_read: &str, file_write: &str) -> Result<()> {
             *r = buf[pos];
         });
         writer.write_all(&res[0..size])?;
-        i += 1;
     }
 
     Ok(())
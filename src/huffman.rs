@@ -1,25 +1,25 @@
-#![allow(dead_code)]
-
 use std::cmp::Reverse;
 use std::collections::BinaryHeap;
+use std::time::Instant;
 use std::vec;
 use std::{
     fs::File,
-    io::{BufReader, BufWriter, Read, Result, Seek, SeekFrom, Write},
+    io::{BufReader, BufWriter, Error, ErrorKind, Read, Result, Seek, SeekFrom, Write},
 };
 
-use bs::{BitStream, Mode};
+use bs::{BitReader, BitStream, BitWriter, Mode};
+use rayon::prelude::*;
 
 #[derive(Clone, Debug)]
-
+#[allow(non_camel_case_types)]
 pub struct node {
     left: Option<usize>,
     right: Option<usize>,
     parent: Option<usize>,
-    freq: u32,
+    freq: u64,
 }
 
-pub fn build_tree(freq: &[u32]) -> Vec<node> {
+pub fn build_tree(freq: &[u64]) -> Vec<node> {
     let mut elements = BinaryHeap::new();
 
     let mut tree = vec![
@@ -40,7 +40,7 @@ pub fn build_tree(freq: &[u32]) -> Vec<node> {
     });
 
     let mut tree_ind = tree.len();
-    while elements.len() != 1 {
+    while elements.len() > 1 {
         let (left, right) = (elements.pop().unwrap().0, elements.pop().unwrap().0);
 
         let freq_ind = left.0 + right.0;
@@ -61,7 +61,7 @@ pub fn build_tree(freq: &[u32]) -> Vec<node> {
     tree
 }
 
-pub fn build_freq_table(file_name: &str) -> Result<(Vec<u32>, u32)> {
+pub fn build_freq_table(file_name: &str) -> Result<(Vec<u64>, u64)> {
     let file = File::open(file_name)?;
     let mut reader = BufReader::new(file);
     let mut freq = vec![0; 256];
@@ -75,64 +75,396 @@ pub fn build_freq_table(file_name: &str) -> Result<(Vec<u32>, u32)> {
     Ok((freq, count_bytes))
 }
 
-fn build_table_code(tree: &[node]) -> Vec<(Vec<u8>, usize)> {
-    let mut table_code: Vec<(Vec<u8>, usize)> = vec![(Vec::new(), 0); 256];
+// Формат файла:
+//   v1 (старий): 256 x u32 частоти, u32 кількість байтів, далі коди.
+//   v2: "HUF", байт версії, u32 кількість байтів, далі бітовий потік з довжинами
+//       канонічних кодів і самими кодами.
+//   v3: як v2, але кількість байтів u64, щоб підтримати файли більші за 4 GiB.
+//   4: адаптивний код, одразу після "HUF" і цього байта йде бітовий потік.
+//   5: блоковий код з кількома таблицями і селекторами, як у bzip2.
+//   6: послідовність символів довільного алфавіту: u32 розмір алфавіту,
+//      u64 кількість символів, далі як у v3.
+//   7: контекстний код першого порядку: u64 кількість байтів, далі бітовий потік.
+//   8: незалежні шматки для паралельної обробки: u64 кількість байтів,
+//      u32 розмір шматка, u32 довжина блока довжин кодів і сам блок, далі для
+//      кожного шматка u32 довжина в байтах і його коди з початку байта.
+const MAGIC: &[u8; 3] = b"HUF";
+const VERSION: u8 = 3;
+const ADAPTIVE: u8 = 4;
+const BLOCKS: u8 = 5;
+const SYMBOLS: u8 = 6;
+const ORDER1: u8 = 7;
+const CHUNKED: u8 = 8;
+const LEGACY_HEADER: u64 = 256 * 4 + 4;
+
+pub const MAX_CODE_LEN: usize = 15;
+
+enum Item {
+    Leaf(usize),
+    Package(usize, usize),
+}
+
+// Довжини кодів, обмежені max_len бітами (алгоритм package-merge).
+// Рівень 0 містить лише листки, кожен наступний - листки і пари з попереднього.
+pub fn limited_code_lengths(freq: &[u64], max_len: usize) -> Vec<u8> {
+    let mut lengths = vec![0u8; freq.len()];
+    let mut leaves: Vec<(u64, usize)> = freq
+        .iter()
+        .enumerate()
+        .filter(|(_, x)| **x != 0)
+        .map(|(i, &x)| (x, i))
+        .collect();
+    leaves.sort();
 
-    table_code.iter_mut().enumerate().for_each(|(i, x)| {
-        let mut child = i;
-        let mut code: Vec<u8> = Vec::new();
-        while let Some(parent) = tree[child].parent {
-            if tree[parent].right.unwrap() == child {
-                code.push(1);
+    let n = leaves.len();
+    match leaves.as_slice() {
+        [] => return lengths,
+        // Один символ все одно отримує однобітний код, інакше його не видно в потоці.
+        [(_, i)] => {
+            lengths[*i] = 1;
+            return lengths;
+        }
+        _ => {}
+    }
+    assert!(
+        max_len < usize::BITS as usize && (1usize << max_len) >= n,
+        "HUF: {n} символів не вміщуються в коди довжиною до {max_len} біт"
+    );
+
+    let mut levels: Vec<Vec<(u64, Item)>> = Vec::with_capacity(max_len);
+    levels.push(leaves.iter().map(|&(w, i)| (w, Item::Leaf(i))).collect());
+    for _ in 1..max_len {
+        let prev = levels.last().unwrap();
+        let packages = prev
+            .chunks_exact(2)
+            .enumerate()
+            .map(|(i, pair)| (pair[0].0 + pair[1].0, Item::Package(2 * i, 2 * i + 1)));
+
+        let mut level = Vec::with_capacity(n + prev.len() / 2);
+        let mut leaves = leaves.iter().peekable();
+        let mut packages = packages.peekable();
+        loop {
+            let take_leaf = match (leaves.peek(), packages.peek()) {
+                (Some(leaf), Some(package)) => leaf.0 <= package.0,
+                (Some(_), None) => true,
+                (None, Some(_)) => false,
+                (None, None) => break,
+            };
+            if take_leaf {
+                let &(w, i) = leaves.next().unwrap();
+                level.push((w, Item::Leaf(i)));
             } else {
-                code.push(0);
+                level.push(packages.next().unwrap());
             }
-            child = parent;
         }
-        code.reverse();
+        levels.push(level);
+    }
 
-        let seq_code = code
-            .chunks(8)
-            .map(|chunk| {
-                chunk
-                    .iter()
-                    .enumerate()
-                    .fold(0u8, |acc, (i, &b)| acc | (b << i))
-            })
-            .collect();
-        *x = (seq_code, code.len());
-    });
+    fn mark(levels: &[Vec<(u64, Item)>], level: usize, index: usize, lengths: &mut [u8]) {
+        match levels[level][index].1 {
+            Item::Leaf(i) => lengths[i] += 1,
+            Item::Package(a, b) => {
+                mark(levels, level - 1, a, lengths);
+                mark(levels, level - 1, b, lengths);
+            }
+        }
+    }
 
-    table_code
+    let top = levels.len() - 1;
+    for index in 0..2 * n - 2 {
+        mark(&levels, top, index, &mut lengths);
+    }
+
+    lengths
 }
 
-fn write_freq_table(freq: &[u32], count_bytes: u32, file_write: &str) -> Result<File> {
-    let file_write = File::create(file_write)?;
-    let mut writer = BufWriter::new(&file_write);
-    freq.iter()
-        .try_for_each(|x| writer.write_all(&x.to_le_bytes()))?;
+// Канонічні коди: символи з однаковою довжиною йдуть за зростанням, коди
+// кожної довжини - послідовні числа. Біти коду записані у зворотному порядку,
+// щоб перший біт коду потрапив у молодший розряд, як у BitStream.
+fn canonical_codes(lengths: &[u8]) -> Vec<(u32, usize)> {
+    let max_len = lengths.iter().copied().max().unwrap_or(0) as usize;
+    let mut bl_count = vec![0u32; max_len + 1];
+    lengths
+        .iter()
+        .filter(|&&l| l != 0)
+        .for_each(|&l| bl_count[l as usize] += 1);
 
-    writer.write_all(&count_bytes.to_le_bytes())?;
-    writer.flush()?;
-    drop(writer);
+    let mut next_code = vec![0u32; max_len + 1];
+    let mut code = 0u32;
+    for len in 1..=max_len {
+        code = (code + bl_count[len - 1]) << 1;
+        next_code[len] = code;
+    }
+
+    lengths
+        .iter()
+        .map(|&len| {
+            let len = len as usize;
+            if len == 0 {
+                return (0, 0);
+            }
+            let code = next_code[len];
+            // після останнього 32-бітного коду повного дерева лічильник виходить за u32
+            next_code[len] = code.wrapping_add(1);
+            (code.reverse_bits() >> (32 - len), len)
+        })
+        .collect()
+}
 
-    Ok(file_write)
+fn read_bits(bs: &mut BitStream, bit_len: usize) -> Result<u32> {
+    let seq = bs.read_bit_sequence(bit_len)?;
+    Ok(seq
+        .iter()
+        .enumerate()
+        .fold(0u32, |acc, (i, &x)| acc | ((x as u32) << (8 * i))))
+}
+
+// Довжини кодів пишуться як у bzip2: спершу бітова карта використаних символів
+// (біт на групу з 16 символів і 16 біт на кожну непорожню групу), далі довжина
+// першого символу в 8 бітах і різниці між сусідніми довжинами: "10" - +1,
+// "11" - -1, "0" - наступний символ.
+fn write_lengths(mut write_bits: impl FnMut(u64, usize) -> Result<()>, lengths: &[u8]) -> Result<()> {
+    let groups: Vec<&[u8]> = lengths.chunks(16).collect();
+    for group in &groups {
+        write_bits(group.iter().any(|&l| l != 0) as u64, 1)?;
+    }
+    for group in groups.iter().filter(|g| g.iter().any(|&l| l != 0)) {
+        for &l in group.iter() {
+            write_bits((l != 0) as u64, 1)?;
+        }
+    }
+
+    let mut used = lengths.iter().copied().filter(|&l| l != 0).peekable();
+    let mut cur = match used.peek() {
+        Some(&first) => first,
+        None => return Ok(()),
+    };
+    write_bits(cur as u64, 8)?;
+    for len in used {
+        while cur < len {
+            write_bits(0b01, 2)?;
+            cur += 1;
+        }
+        while cur > len {
+            write_bits(0b11, 2)?;
+            cur -= 1;
+        }
+        write_bits(0, 1)?;
+    }
+
+    Ok(())
+}
+
+fn read_lengths(mut read_bits: impl FnMut(usize) -> Result<u32>, n: usize) -> Result<Vec<u8>> {
+    let groups = n.div_ceil(16);
+    let mut used_groups = Vec::with_capacity(groups);
+    for _ in 0..groups {
+        used_groups.push(read_bits(1)? == 1);
+    }
+
+    let mut used = vec![false; n];
+    for (g, _) in used_groups.iter().enumerate().filter(|(_, u)| **u) {
+        for flag in used[g * 16..((g + 1) * 16).min(n)].iter_mut() {
+            *flag = read_bits(1)? == 1;
+        }
+    }
+
+    let mut lengths = vec![0u8; n];
+    if !used.contains(&true) {
+        return Ok(lengths);
+    }
+    let mut cur = read_bits(8)? as i32;
+    for (i, _) in used.iter().enumerate().filter(|(_, u)| **u) {
+        while read_bits(1)? == 1 {
+            cur += if read_bits(1)? == 0 { 1 } else { -1 };
+            if !(1..=255).contains(&cur) {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    "HUF: некоректна довжина коду в заголовку",
+                ));
+            }
+        }
+        lengths[i] = cur as u8;
+    }
+
+    // пошкоджений заголовок може дати довжини, з яких не скласти префіксний
+    // код; таблиця декодера на таких довжинах виходить за межі
+    if lengths.iter().any(|&len| len > 32)
+        || lengths
+            .iter()
+            .filter(|&&len| len != 0)
+            .map(|&len| 1u64 << (32 - len))
+            .sum::<u64>()
+            > 1 << 32
+    {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            "HUF: довжини кодів не утворюють префіксного коду",
+        ));
+    }
+
+    Ok(lengths)
+}
+
+// Побітовий декодер канонічного коду: для кожної довжини знаємо кількість
+// кодів, тому дерево не потрібне. Використовується лише в bench_decode
+// (report huffman-decode).
+struct Canonical {
+    count: Vec<u64>,
+    symbols: Vec<usize>,
+}
+
+impl Canonical {
+    fn new(lengths: &[u8]) -> Self {
+        let max_len = lengths.iter().copied().max().unwrap_or(0) as usize;
+        let mut count = vec![0u64; max_len + 1];
+        lengths
+            .iter()
+            .filter(|&&l| l != 0)
+            .for_each(|&l| count[l as usize] += 1);
+
+        let mut symbols: Vec<usize> = (0..lengths.len()).filter(|&i| lengths[i] != 0).collect();
+        symbols.sort_by_key(|&i| lengths[i]);
+
+        Self { count, symbols }
+    }
+
+    fn decode_symbol(&self, bs: &mut BitStream) -> Result<usize> {
+        let (mut code, mut first, mut index) = (0u64, 0u64, 0u64);
+        for len in 1..self.count.len() {
+            code |= read_bits(bs, 1)? as u64;
+            let count = self.count[len];
+            if code - first < count {
+                return Ok(self.symbols[(index + code - first) as usize]);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+
+        Err(Error::new(
+            ErrorKind::InvalidData,
+            "HUF: послідовність бітів не відповідає жодному коду",
+        ))
+    }
+}
+
+const TABLE_BITS: usize = 10;
+
+#[derive(Clone, Copy, Default)]
+struct Entry {
+    // символ, або зсув таблиці другого рівня якщо sub
+    value: u32,
+    // довжина коду, або кількість бітів індексу таблиці другого рівня
+    len: u8,
+    sub: bool,
+}
+
+// Табличний декодер: перші TABLE_BITS бітів одразу дають символ і довжину коду,
+// довші коди дочитуються з таблиці другого рівня для свого префікса.
+struct DecodeTable {
+    primary: Vec<Entry>,
+    secondary: Vec<Entry>,
+    max_len: usize,
+}
+
+impl DecodeTable {
+    fn new(lengths: &[u8]) -> Self {
+        let codes = canonical_codes(lengths);
+        let max_len = lengths.iter().copied().max().unwrap_or(0) as usize;
+        let mut primary = vec![Entry::default(); 1 << TABLE_BITS];
+        let mut secondary = Vec::new();
+
+        // найдовший код для кожного префікса, що не вміщується в першу таблицю
+        let mut sub_bits = vec![0usize; 1 << TABLE_BITS];
+        for &(code, len) in codes.iter().filter(|(_, len)| *len > TABLE_BITS) {
+            let prefix = code as usize & ((1 << TABLE_BITS) - 1);
+            sub_bits[prefix] = sub_bits[prefix].max(len - TABLE_BITS);
+        }
+        for (prefix, &bits) in sub_bits.iter().enumerate().filter(|(_, b)| **b != 0) {
+            primary[prefix] = Entry {
+                value: secondary.len() as u32,
+                len: bits as u8,
+                sub: true,
+            };
+            secondary.resize(secondary.len() + (1 << bits), Entry::default());
+        }
+
+        for (sym, &(code, len)) in codes.iter().enumerate().filter(|(_, (_, len))| *len != 0) {
+            let code = code as usize;
+            let entry = Entry {
+                value: sym as u32,
+                len: len as u8,
+                sub: false,
+            };
+            if len <= TABLE_BITS {
+                for k in 0..1 << (TABLE_BITS - len) {
+                    primary[code | (k << len)] = entry;
+                }
+            } else {
+                let sub = primary[code & ((1 << TABLE_BITS) - 1)];
+                let rest = code >> TABLE_BITS;
+                let rest_len = len - TABLE_BITS;
+                for k in 0..1 << (sub.len as usize - rest_len) {
+                    secondary[sub.value as usize + (rest | (k << rest_len))] = entry;
+                }
+            }
+        }
+
+        Self {
+            primary,
+            secondary,
+            max_len,
+        }
+    }
+
+    fn decode_symbol<R: Read>(&self, br: &mut BitReader<R>) -> Result<usize> {
+        let bits = br.peek(self.max_len)? as usize;
+        let mut entry = self.primary[bits & ((1 << TABLE_BITS) - 1)];
+        if entry.sub {
+            let index = (bits >> TABLE_BITS) & ((1 << entry.len) - 1);
+            entry = self.secondary[entry.value as usize + index];
+        }
+        if entry.len == 0 {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "HUF: послідовність бітів не відповідає жодному коду",
+            ));
+        }
+
+        br.consume(entry.len as usize)?;
+        Ok(entry.value as usize)
+    }
 }
 
 pub fn encode(file_read: &str, file_write: &str) -> Result<()> {
+    encode_limited(file_read, file_write, MAX_CODE_LEN)
+}
+
+pub fn encode_limited(file_read: &str, file_write: &str, max_len: usize) -> Result<()> {
+    if !(8..=32).contains(&max_len) {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "HUF: максимальна довжина коду має бути від 8 до 32 біт",
+        ));
+    }
     let (freq, count_bytes) = build_freq_table(file_read)?;
-    let file_write = write_freq_table(&freq, count_bytes, file_write)?;
+    let lengths = limited_code_lengths(&freq, max_len);
+    let table_code = canonical_codes(&lengths);
+
+    let mut file_write = File::create(file_write)?;
+    file_write.write_all(MAGIC)?;
+    file_write.write_all(&[VERSION])?;
+    file_write.write_all(&count_bytes.to_le_bytes())?;
+
     let mut bs = BitStream::new_file(file_write, Mode::Write);
+    write_lengths(|v, k| bs.write_bit_sequence(&v.to_le_bytes(), k), &lengths)?;
 
-    let tree = build_tree(&freq);
-    let table_code = build_table_code(&tree);
     let mut reader = BufReader::new(File::open(file_read)?);
     let mut byte = [0];
     while reader.read_exact(&mut byte).is_ok() {
-        bs.write_bit_sequence(
-            &table_code[byte[0] as usize].0,
-            table_code[byte[0] as usize].1,
-        )?;
+        let (code, len) = table_code[byte[0] as usize];
+        bs.write_bit_sequence(&code.to_le_bytes(), len)?;
     }
 
     bs.close()?;
@@ -160,13 +492,101 @@ fn read_freq_table(file_read: &str) -> Result<(Vec<u32>, u32, File)> {
 }
 
 pub fn decode(file_read: &str, file_write: &str) -> Result<()> {
+    let mut file = File::open(file_read)?;
+    let mut magic = [0u8; 4];
+    if file.read_exact(&mut magic).is_err() || &magic[..3] != MAGIC {
+        return decode_legacy(file_read, file_write);
+    }
+
+    match magic[3] {
+        2 => decode_canonical(file, file_write, 4),
+        VERSION => decode_canonical(file, file_write, 8),
+        ADAPTIVE => {
+            let mut writer = BufWriter::new(File::create(file_write)?);
+            decode_adaptive_body(BufReader::new(file), &mut writer)?;
+            writer.flush()
+        }
+        BLOCKS => {
+            let mut writer = BufWriter::new(File::create(file_write)?);
+            decode_blocks_body(file, &mut writer)?;
+            writer.flush()
+        }
+        SYMBOLS => decode_symbols_file(file, file_write),
+        ORDER1 => decode_order1(file, file_write),
+        CHUNKED => decode_chunked(file, file_write),
+        v => Err(Error::new(
+            ErrorKind::InvalidData,
+            format!("HUF: непідтримувана версія формату {v}"),
+        )),
+    }
+}
+
+fn decode_canonical(mut file: File, file_write: &str, count_len: usize) -> Result<()> {
+    let mut buf = [0u8; 8];
+    file.read_exact(&mut buf[..count_len])?;
+    let mut count_bytes = u64::from_le_bytes(buf);
+
+    let mut br = BitReader::new(file);
+    let lengths = read_lengths(|k| Ok(br.read_bits(k)? as u32), 256)?;
+    let table = DecodeTable::new(&lengths);
+
+    let mut writer = BufWriter::new(File::create(file_write)?);
+    while count_bytes != 0 {
+        let byte = table.decode_symbol(&mut br)?;
+        writer.write_all(&[byte as u8])?;
+        count_bytes -= 1;
+    }
+    writer.flush()?;
+
+    Ok(())
+}
+
+// Канонічний декодер, що читає код по біту, - точка відліку для табличного
+// декодера у звіті huffman-decode. Це не колишній декодер: той розбирав
+// старий формат файлу і з нинішнім не працює.
+fn decode_bitwise(file_read: &str, file_write: &str) -> Result<()> {
+    let mut file = File::open(file_read)?;
+    let mut header = [0u8; 12];
+    file.read_exact(&mut header)?;
+    let mut count_bytes = u64::from_le_bytes(header[4..12].try_into().unwrap());
+
+    let mut bs = BitStream::new_file(file, Mode::Read);
+    let lengths = read_lengths(|k| read_bits(&mut bs, k), 256)?;
+    let canonical = Canonical::new(&lengths);
+
+    let mut writer = BufWriter::new(File::create(file_write)?);
+    while count_bytes != 0 {
+        let byte = canonical.decode_symbol(&mut bs)?;
+        writer.write_all(&[byte as u8])?;
+        count_bytes -= 1;
+    }
+    writer.flush()?;
+
+    Ok(())
+}
+
+fn decode_legacy(file_read: &str, file_write: &str) -> Result<()> {
     let (freq, mut count_bytes, _) = read_freq_table(file_read)?;
+    let freq: Vec<u64> = freq.iter().map(|&x| x as u64).collect();
+    let mut writer = BufWriter::new(File::create(file_write)?);
+    if count_bytes == 0 {
+        return writer.flush();
+    }
+
+    // Старий кодер давав єдиному символу код нульової довжини і не писав бітів.
+    let used: Vec<usize> = (0..freq.len()).filter(|&i| freq[i] != 0).collect();
+    if let [byte] = used[..] {
+        for _ in 0..count_bytes {
+            writer.write_all(&[byte as u8])?;
+        }
+        return writer.flush();
+    }
+
     let tree = build_tree(&freq);
     let mut file_read = File::open(file_read)?;
-    file_read.seek(SeekFrom::Start(1028))?;
+    file_read.seek(SeekFrom::Start(LEGACY_HEADER))?;
 
     let mut bs = BitStream::new_file(file_read, Mode::Read);
-    let mut writer = BufWriter::new(File::create(file_write)?);
     let mut cur_node = tree.len() - 1;
 
     while let Ok(seq) = bs.read_bit_sequence(1) {
@@ -179,7 +599,7 @@ pub fn decode(file_read: &str, file_write: &str) -> Result<()> {
                     panic!("Should be only 0 or 1")
                 }
             };
-            if cur_node < 256 {
+            if cur_node < freq.len() {
                 writer.write_all(&[cur_node as u8])?;
                 cur_node = tree.len() - 1;
                 count_bytes -= 1;
@@ -194,278 +614,1456 @@ pub fn decode(file_read: &str, file_write: &str) -> Result<()> {
     Ok(())
 }
 
-fn fun_mtf(types: &str, num: &str) -> Result<()> {
-    let test_path = "test_files_mtf/".to_string() + types + "/test" + num + ".mtf";
-    let test_path_out = "test_files/".to_string() + types + "/test" + num + ".mhuf";
-    // let test_path_decode = "test_files/".to_string() + types + "/test" + num + ".decmtf";
-    encode(&test_path, &test_path_out)?;
-    println!("Encoded: {types}.{num}");
-    // decode(&test_path_out, &test_path_decode)?;
-    // println!("Decoded");
+// Аналіз коду для таблиці частот: наскільки середня довжина коду близька до
+// ентропії.
+pub struct Analysis {
+    pub freq: Vec<u64>,
+    pub lengths: Vec<u8>,
+    codes: Vec<(u32, usize)>,
+    pub total: u64,
+    // біт на символ
+    pub entropy: f64,
+    pub average_len: f64,
+    pub redundancy: f64,
+    pub kraft_sum: f64,
+}
+
+impl Analysis {
+    // Сума Крафта не перевищує 1, тобто коди префіксні.
+    pub fn kraft_ok(&self) -> bool {
+        self.kraft_sum <= 1.0 + 1e-12
+    }
+
+    // Розмір файлу, який дасть encode на цих частотах: заголовок з лічильником,
+    // таблиця довжин і коди, доповнені до цілого байта.
+    pub fn encoded_size(&self) -> u64 {
+        let bits = lengths_header_bits(&self.lengths) + cost(&self.freq, &self.lengths);
+        (MAGIC.len() + 1 + 8) as u64 + bits.div_ceil(8)
+    }
+
+    // Код символу у порядку передачі бітів.
+    pub fn code_string(&self, sym: usize) -> String {
+        let (code, len) = self.codes[sym];
+        (0..len)
+            .map(|i| if (code >> i) & 1 == 1 { '1' } else { '0' })
+            .collect()
+    }
+}
+
+pub fn analyze(freq: &[u64], max_len: usize) -> Result<Analysis> {
+    // коди зберігаються в u32
+    let used = freq.iter().filter(|&&x| x != 0).count();
+    let max_len = max_len.max(min_code_len(used));
+    if max_len > 32 {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "HUF: максимальна довжина коду не може перевищувати 32 біти",
+        ));
+    }
+    let lengths = limited_code_lengths(freq, max_len);
+    let codes = canonical_codes(&lengths);
+    let total: u64 = freq.iter().sum();
+
+    let (mut entropy, mut average_len) = (0.0, 0.0);
+    if total != 0 {
+        for (&f, &l) in freq.iter().zip(&lengths).filter(|(f, _)| **f != 0) {
+            let p = f as f64 / total as f64;
+            entropy += p * (1.0 / p).log2();
+            average_len += p * l as f64;
+        }
+    }
+    let kraft_sum = lengths
+        .iter()
+        .filter(|&&l| l != 0)
+        .map(|&l| 0.5f64.powi(l as i32))
+        .sum();
+
+    Ok(Analysis {
+        freq: freq.to_vec(),
+        lengths,
+        codes,
+        total,
+        entropy,
+        average_len,
+        redundancy: average_len - entropy,
+        kraft_sum,
+    })
+}
+
+impl std::fmt::Display for Analysis {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "symbols: {}", self.total)?;
+        writeln!(f, "entropy: {:.4} bits/symbol", self.entropy)?;
+        writeln!(
+            f,
+            "average code length: {:.4} bits/symbol",
+            self.average_len
+        )?;
+        writeln!(f, "redundancy: {:.4} bits/symbol", self.redundancy)?;
+        writeln!(
+            f,
+            "kraft sum: {:.6} ({})",
+            self.kraft_sum,
+            if self.kraft_ok() { "ok" } else { "violated" }
+        )?;
+        writeln!(f, "{:>6} {:>12} {:>4}  code", "symbol", "freq", "len")?;
+        for (sym, &freq) in self.freq.iter().enumerate().filter(|(_, x)| **x != 0) {
+            writeln!(
+                f,
+                "{:>6} {:>12} {:>4}  {}",
+                sym,
+                freq,
+                self.lengths[sym],
+                self.code_string(sym)
+            )?;
+        }
+        Ok(())
+    }
+}
+
+// Довжина коду для алфавіту з n використаними символами: щонайменше MAX_CODE_LEN
+// і достатньо, щоб усі символи отримали коди.
+fn symbols_max_len(n: usize) -> usize {
+    MAX_CODE_LEN.max(min_code_len(n))
+}
+
+// Найменша довжина, за якої n символів ще отримують різні коди.
+fn min_code_len(n: usize) -> usize {
+    (usize::BITS - n.saturating_sub(1).leading_zeros()) as usize
+}
+
+pub fn freq_table<T: Copy + Into<u32>>(symbols: &[T], alphabet: usize) -> Result<Vec<u64>> {
+    let mut freq = vec![0u64; alphabet];
+    for &x in symbols {
+        let x: u32 = x.into();
+        match freq.get_mut(x as usize) {
+            Some(f) => *f += 1,
+            None => {
+                return Err(Error::new(
+                    ErrorKind::InvalidInput,
+                    format!("HUF: символ {x} поза алфавітом розміру {alphabet}"),
+                ));
+            }
+        }
+    }
+    Ok(freq)
+}
+
+// Найбільший алфавіт послідовності символів: під довжини кодів декодер
+// виділяє пам'ять за розміром із заголовка.
+pub const MAX_ALPHABET: usize = 1 << 20;
+
+// Кодує послідовність символів з алфавіту 0..alphabet (байти, коди LZW, вихід
+// MTF з RUNA/RUNB тощо) у самодостатній буфер формату 6.
+pub fn encode_symbols<T: Copy + Into<u32>>(symbols: &[T], alphabet: usize) -> Result<Vec<u8>> {
+    if alphabet == 0 || alphabet > MAX_ALPHABET {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!("HUF: розмір алфавіту має бути від 1 до {MAX_ALPHABET}"),
+        ));
+    }
+    let freq = freq_table(symbols, alphabet)?;
+    let used = freq.iter().filter(|&&x| x != 0).count();
+    let lengths = limited_code_lengths(&freq, symbols_max_len(used));
+    let codes = canonical_codes(&lengths);
+
+    let mut out = Vec::with_capacity(symbols.len() / 2 + 16);
+    out.extend_from_slice(MAGIC);
+    out.push(SYMBOLS);
+    out.extend_from_slice(&(alphabet as u32).to_le_bytes());
+    out.extend_from_slice(&(symbols.len() as u64).to_le_bytes());
+
+    let mut bw = BitWriter::new(out);
+    write_lengths(|v, k| bw.write_bits(v, k), &lengths)?;
+    for &x in symbols {
+        let (code, len) = codes[x.into() as usize];
+        bw.write_bits(code as u64, len)?;
+    }
+    bw.finish()
+}
+
+pub fn decode_symbols<T: TryFrom<u32>>(data: &[u8]) -> Result<Vec<T>> {
+    if data.len() < 4 || &data[..3] != MAGIC || data[3] != SYMBOLS {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            "HUF: буфер не містить послідовності символів",
+        ));
+    }
+    let (alphabet, symbols) = decode_symbols_body(&data[4..])?;
+    symbols
+        .into_iter()
+        .map(|x| {
+            T::try_from(x).map_err(|_| {
+                Error::new(
+                    ErrorKind::InvalidData,
+                    format!("HUF: символ {x} не вміщується в тип (алфавіт {alphabet})"),
+                )
+            })
+        })
+        .collect()
+}
+
+fn decode_symbols_body(mut data: impl Read) -> Result<(usize, Vec<u32>)> {
+    let mut header = [0u8; 12];
+    data.read_exact(&mut header)?;
+    let alphabet = u32::from_le_bytes(header[..4].try_into().unwrap()) as usize;
+    let count = u64::from_le_bytes(header[4..].try_into().unwrap());
+    if alphabet == 0 || alphabet > MAX_ALPHABET {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            format!("HUF: алфавіт {alphabet} у заголовку поза межами 1..={MAX_ALPHABET}"),
+        ));
+    }
+
+    let mut br = BitReader::new(data);
+    let lengths = read_lengths(|k| Ok(br.read_bits(k)? as u32), alphabet)?;
+    let table = DecodeTable::new(&lengths);
+
+    let mut symbols = Vec::with_capacity(count.min(1 << 24) as usize);
+    for _ in 0..count {
+        symbols.push(table.decode_symbol(&mut br)? as u32);
+    }
+    Ok((alphabet, symbols))
+}
+
+// У файл символи пишуться найменшою шириною, що вміщує алфавіт (1, 2 або 4 байти, le).
+fn decode_symbols_file(file: File, file_write: &str) -> Result<()> {
+    let (alphabet, symbols) = decode_symbols_body(BufReader::new(file))?;
+    let width = match alphabet {
+        0..=256 => 1,
+        257..=65536 => 2,
+        _ => 4,
+    };
+    let mut writer = BufWriter::new(File::create(file_write)?);
+    for x in symbols {
+        writer.write_all(&x.to_le_bytes()[..width])?;
+    }
+    writer.flush()
+}
+
+// Контекстний код першого порядку: символ кодується таблицею свого контексту
+// (попереднього байта, для першого байта - 0). Рідкісні контексти, яким власна
+// таблиця не окупається, зливаються в одну спільну таблицю.
+//
+// Потік: 256 бітів "контекст має власну таблицю", довжини кодів спільної
+// таблиці, довжини власних таблиць за зростанням контексту, далі коди.
+fn lengths_header_bits(lengths: &[u8]) -> u64 {
+    let mut bits = 0;
+    write_lengths(
+        |_, k| {
+            bits += k as u64;
+            Ok(())
+        },
+        lengths,
+    )
+    .unwrap();
+    bits
+}
+
+fn cost(freq: &[u64], lengths: &[u8]) -> u64 {
+    freq.iter().zip(lengths).map(|(&f, &l)| f * l as u64).sum()
+}
+
+// Повертає номер таблиці для кожного контексту і довжини кодів таблиць
+// (таблиця 0 - спільна).
+fn order1_tables(ctx_freq: &[Vec<u64>]) -> (Vec<usize>, Vec<Vec<u8>>) {
+    let mut total = vec![0u64; 256];
+    ctx_freq
+        .iter()
+        .for_each(|f| f.iter().enumerate().for_each(|(i, &x)| total[i] += x));
+    let order0 = limited_code_lengths(&total, MAX_CODE_LEN);
+
+    let mut map = vec![0; ctx_freq.len()];
+    let mut tables = vec![Vec::new()];
+    let mut shared = vec![0u64; 256];
+    for (ctx, freq) in ctx_freq.iter().enumerate() {
+        if freq.iter().all(|&x| x == 0) {
+            continue;
+        }
+        let own = limited_code_lengths(freq, MAX_CODE_LEN);
+        if cost(freq, &own) + lengths_header_bits(&own) < cost(freq, &order0) {
+            map[ctx] = tables.len();
+            tables.push(own);
+        } else {
+            freq.iter().enumerate().for_each(|(i, &x)| shared[i] += x);
+        }
+    }
+    tables[0] = limited_code_lengths(&shared, MAX_CODE_LEN);
+
+    (map, tables)
+}
+
+pub fn encode_order1(file_read: &str, file_write: &str) -> Result<()> {
+    let mut ctx_freq = vec![vec![0u64; 256]; 256];
+    let mut count_bytes = 0u64;
+    let mut prev = 0usize;
+    let mut reader = File::open(file_read)?;
+    let mut buf = vec![0u8; 64 * 1024];
+    loop {
+        let n = reader.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        for &x in &buf[..n] {
+            ctx_freq[prev][x as usize] += 1;
+            prev = x as usize;
+        }
+        count_bytes += n as u64;
+    }
+
+    let (map, tables) = order1_tables(&ctx_freq);
+    let codes: Vec<Vec<(u32, usize)>> = tables.iter().map(|l| canonical_codes(l)).collect();
+
+    let mut writer = BufWriter::new(File::create(file_write)?);
+    writer.write_all(MAGIC)?;
+    writer.write_all(&[ORDER1])?;
+    writer.write_all(&count_bytes.to_le_bytes())?;
+    let mut bw = BitWriter::new(writer);
+    for &t in &map {
+        bw.write_bits((t != 0) as u64, 1)?;
+    }
+    for table in &tables {
+        write_lengths(|v, k| bw.write_bits(v, k), table)?;
+    }
+
+    let mut reader = File::open(file_read)?;
+    let mut prev = 0usize;
+    loop {
+        let n = reader.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        for &x in &buf[..n] {
+            let (code, len) = codes[map[prev]][x as usize];
+            bw.write_bits(code as u64, len)?;
+            prev = x as usize;
+        }
+    }
+    bw.finish()?;
+
+    Ok(())
+}
+
+fn decode_order1(mut file: File, file_write: &str) -> Result<()> {
+    let mut buf = [0u8; 8];
+    file.read_exact(&mut buf)?;
+    let count_bytes = u64::from_le_bytes(buf);
+
+    let mut br = BitReader::new(file);
+    let mut map = vec![0; 256];
+    let mut n_tables = 1;
+    for t in map.iter_mut() {
+        if br.read_bits(1)? == 1 {
+            *t = n_tables;
+            n_tables += 1;
+        }
+    }
+    let mut tables = Vec::with_capacity(n_tables);
+    for _ in 0..n_tables {
+        let lengths = read_lengths(|k| Ok(br.read_bits(k)? as u32), 256)?;
+        tables.push(DecodeTable::new(&lengths));
+    }
+
+    let mut writer = BufWriter::new(File::create(file_write)?);
+    let mut prev = 0usize;
+    for _ in 0..count_bytes {
+        let x = tables[map[prev]].decode_symbol(&mut br)?;
+        writer.write_all(&[x as u8])?;
+        prev = x;
+    }
+    writer.flush()
+}
+
+// Паралельний режим: одна таблиця на весь файл, але кожен шматок по chunk
+// байтів кодується з початку байта і має довжину в заголовку, тому шматки
+// кодуються і декодуються незалежно. Результат не залежить від кількості потоків.
+pub const MAX_CHUNK: usize = 64 << 20;
+// скільки байтів даних в одній порції; порція містить щонайменше один шматок
+const CHUNK_BATCH_BYTES: usize = 64 << 20;
+
+fn read_chunk(reader: &mut impl Read, buf: &mut [u8]) -> Result<usize> {
+    let mut n = 0;
+    while n < buf.len() {
+        let read = reader.read(&mut buf[n..])?;
+        if read == 0 {
+            break;
+        }
+        n += read;
+    }
+    Ok(n)
+}
+
+pub fn encode_chunked(file_read: &str, file_write: &str, chunk: usize) -> Result<()> {
+    if chunk == 0 || chunk > MAX_CHUNK {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!("HUF: розмір шматка має бути від 1 до {MAX_CHUNK} байт"),
+        ));
+    }
+    let (freq, count_bytes) = build_freq_table(file_read)?;
+    let lengths = limited_code_lengths(&freq, MAX_CODE_LEN);
+    let codes = canonical_codes(&lengths);
+
+    let mut writer = BufWriter::new(File::create(file_write)?);
+    writer.write_all(MAGIC)?;
+    writer.write_all(&[CHUNKED])?;
+    writer.write_all(&count_bytes.to_le_bytes())?;
+    writer.write_all(&(chunk as u32).to_le_bytes())?;
+    let mut bw = BitWriter::new(Vec::new());
+    write_lengths(|v, k| bw.write_bits(v, k), &lengths)?;
+    let header = bw.finish()?;
+    writer.write_all(&(header.len() as u32).to_le_bytes())?;
+    writer.write_all(&header)?;
+
+    let mut reader = File::open(file_read)?;
+    let mut buf = vec![0u8; chunk];
+    loop {
+        // 1) Збираємо порцію шматків
+        let mut batch: Vec<Vec<u8>> = Vec::new();
+        let mut batch_bytes = 0;
+        while batch.is_empty() || batch_bytes + chunk <= CHUNK_BATCH_BYTES {
+            let n = read_chunk(&mut reader, &mut buf)?;
+            if n == 0 {
+                break;
+            }
+            batch.push(buf[..n].to_vec());
+            batch_bytes += n;
+        }
+        if batch.is_empty() {
+            break;
+        }
+
+        // 2) Паралельне кодування
+        let results: Vec<Result<Vec<u8>>> = batch
+            .par_iter()
+            .map(|part| {
+                let mut bw = BitWriter::new(Vec::with_capacity(part.len()));
+                for &x in part {
+                    let (code, len) = codes[x as usize];
+                    bw.write_bits(code as u64, len)?;
+                }
+                bw.finish()
+            })
+            .collect();
+
+        // 3) Послідовний запис
+        for res in results {
+            let res = res?;
+            writer.write_all(&(res.len() as u32).to_le_bytes())?;
+            writer.write_all(&res)?;
+        }
+    }
+    writer.flush()?;
+
     Ok(())
 }
 
-fn fun_huf(types: &str, num: &str) -> Result<()> {
-    let test_path = "test_files/".to_string() + types + "/test" + num + "." + types;
-    let test_path_out = "test_files/".to_string() + types + "/test" + num + ".huf";
-    // let test_path_decode = "test_files/".to_string() + types + "/test" + num + ".decmtf";
-    encode(&test_path, &test_path_out)?;
-    println!("Encoded: {types}.{num}");
-    // decode(&test_path_out, &test_path_decode)?;
-    // println!("Decoded");
+fn decode_chunked(file: File, file_write: &str) -> Result<()> {
+    let mut reader = BufReader::new(file);
+    let mut header = [0u8; 16];
+    reader.read_exact(&mut header)?;
+    let mut count_bytes = u64::from_le_bytes(header[..8].try_into().unwrap());
+    let chunk = u32::from_le_bytes(header[8..12].try_into().unwrap()) as u64;
+    let lengths_len = u32::from_le_bytes(header[12..16].try_into().unwrap()) as usize;
+    if chunk == 0 || chunk > MAX_CHUNK as u64 {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            "HUF: недопустимий розмір шматка в заголовку",
+        ));
+    }
+
+    let mut lengths = Vec::new();
+    (&mut reader).take(lengths_len as u64).read_to_end(&mut lengths)?;
+    if lengths.len() != lengths_len {
+        return Err(Error::new(ErrorKind::UnexpectedEof, "HUF: файл обірвано"));
+    }
+    let mut br = BitReader::new(&lengths[..]);
+    let lengths = read_lengths(|k| Ok(br.read_bits(k)? as u32), 256)?;
+    let max_len = lengths.iter().copied().max().unwrap_or(0) as u64;
+    let table = DecodeTable::new(&lengths);
+
+    let mut writer = BufWriter::new(File::create(file_write)?);
+    while count_bytes != 0 {
+        // 1) Збираємо порцію шматків
+        let mut batch: Vec<(Vec<u8>, usize)> = Vec::new();
+        let mut batch_bytes = 0;
+        while count_bytes != 0 && (batch.is_empty() || batch_bytes + chunk <= CHUNK_BATCH_BYTES as u64) {
+            let n = count_bytes.min(chunk);
+            let mut len = [0u8; 4];
+            reader.read_exact(&mut len)?;
+            // n символів займають не більше n * max_len бітів
+            let len = u32::from_le_bytes(len) as u64;
+            if len > (n * max_len).div_ceil(8) {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    "HUF: шматок довший, ніж можливо для його кількості байтів",
+                ));
+            }
+            let mut part = vec![0u8; len as usize];
+            reader.read_exact(&mut part)?;
+            batch.push((part, n as usize));
+            batch_bytes += n;
+            count_bytes -= n;
+        }
+
+        // 2) Паралельне декодування
+        let results: Vec<Result<Vec<u8>>> = batch
+            .par_iter()
+            .map(|(part, n)| {
+                let mut br = BitReader::new(&part[..]);
+                let mut out = Vec::with_capacity(*n);
+                for _ in 0..*n {
+                    out.push(table.decode_symbol(&mut br)? as u8);
+                }
+                Ok(out)
+            })
+            .collect();
+
+        // 3) Послідовний запис
+        for res in results {
+            writer.write_all(&res?)?;
+        }
+    }
+    writer.flush()?;
+
     Ok(())
 }
 
-fn fun_bwt(types: &str, num: &str) -> Result<()> {
-    let test_path = "test_files_bwt/".to_string() + types + "/test" + num + ".bwt";
-    let test_path_out = "test_files/".to_string() + types + "/test" + num + ".bhuf";
-    // let test_path_decode = "test_files/".to_string() + types + "/test" + num + ".decbwt";
-    encode(&test_path, &test_path_out)?;
-    println!("Encoded: {types}.{num}");
-    // decode(&test_path_out, &test_path_decode)?;
-    // println!("Decoded");
+// Адаптивний код Хаффмана (алгоритм FGK). Кодер і декодер однаково
+// оновлюють дерево після кожного символу, тому частоти не зберігаються, а
+// вхід читається за один прохід. Новий символ передається кодом NYT і 9 бітами
+// значення; значення ADAPTIVE_EOF позначає кінець потоку.
+const ADAPTIVE_EOF: usize = 256;
+const ADAPTIVE_SYMBOLS: usize = 257;
+const NONE: usize = usize::MAX;
+
+// Номер вузла - його індекс: ваги не спадають зі зростанням індексу, корінь
+// має найбільший номер.
+struct AdaptiveTree {
+    weight: Vec<u64>,
+    parent: Vec<usize>,
+    left: Vec<usize>,
+    right: Vec<usize>,
+    symbol: Vec<usize>,
+    leaf: Vec<usize>,
+    nyt: usize,
+}
+
+impl AdaptiveTree {
+    fn new() -> Self {
+        let size = 2 * ADAPTIVE_SYMBOLS - 1;
+        Self {
+            weight: vec![0; size],
+            parent: vec![NONE; size],
+            left: vec![NONE; size],
+            right: vec![NONE; size],
+            symbol: vec![NONE; size],
+            leaf: vec![NONE; ADAPTIVE_SYMBOLS],
+            nyt: size - 1,
+        }
+    }
+
+    fn root(&self) -> usize {
+        self.weight.len() - 1
+    }
+
+    fn is_leaf(&self, node: usize) -> bool {
+        self.left[node] == NONE
+    }
+
+    // Шлях від кореня до вузла: біти в порядку передачі.
+    fn path(&self, mut node: usize, bits: &mut Vec<u8>) {
+        bits.clear();
+        while self.parent[node] != NONE {
+            let parent = self.parent[node];
+            bits.push((self.right[parent] == node) as u8);
+            node = parent;
+        }
+        bits.reverse();
+    }
+
+    fn swap(&mut self, a: usize, b: usize) {
+        self.weight.swap(a, b);
+        self.left.swap(a, b);
+        self.right.swap(a, b);
+        self.symbol.swap(a, b);
+        for node in [a, b] {
+            if self.is_leaf(node) {
+                if self.symbol[node] != NONE {
+                    self.leaf[self.symbol[node]] = node;
+                }
+            } else {
+                let (l, r) = (self.left[node], self.right[node]);
+                self.parent[l] = node;
+                self.parent[r] = node;
+            }
+        }
+        if self.nyt == a {
+            self.nyt = b;
+        } else if self.nyt == b {
+            self.nyt = a;
+        }
+    }
+
+    fn update(&mut self, sym: usize) {
+        let mut node = self.leaf[sym];
+        if node == NONE {
+            let old = self.nyt;
+            let (leaf, nyt) = (old - 1, old - 2);
+            self.left[old] = nyt;
+            self.right[old] = leaf;
+            self.parent[leaf] = old;
+            self.parent[nyt] = old;
+            self.symbol[leaf] = sym;
+            self.leaf[sym] = leaf;
+            self.nyt = nyt;
+            node = leaf;
+        }
+
+        while node != NONE {
+            // лідер блока - вузол з найбільшим номером серед вузлів тієї ж ваги
+            let mut leader = node;
+            while leader + 1 < self.weight.len() && self.weight[leader + 1] == self.weight[node] {
+                leader += 1;
+            }
+            if leader != node && leader != self.parent[node] {
+                self.swap(node, leader);
+                node = leader;
+            }
+            self.weight[node] += 1;
+            node = self.parent[node];
+        }
+    }
+}
+
+pub fn encode_adaptive(file_read: &str, file_write: &str) -> Result<()> {
+    let reader = BufReader::new(File::open(file_read)?);
+    let writer = BufWriter::new(File::create(file_write)?);
+    encode_adaptive_stream(reader, writer)
+}
+
+pub fn encode_adaptive_stream(mut reader: impl Read, mut writer: impl Write) -> Result<()> {
+    writer.write_all(MAGIC)?;
+    writer.write_all(&[ADAPTIVE])?;
+    let mut bw = BitWriter::new(writer);
+    let mut tree = AdaptiveTree::new();
+    let mut bits = Vec::new();
+
+    let mut put = |tree: &AdaptiveTree, bw: &mut BitWriter<_>, sym: usize| -> Result<()> {
+        let node = tree.leaf[sym];
+        if node == NONE {
+            tree.path(tree.nyt, &mut bits);
+        } else {
+            tree.path(node, &mut bits);
+        }
+        bits.iter().try_for_each(|&b| bw.write_bits(b as u64, 1))?;
+        if node == NONE {
+            bw.write_bits(sym as u64, 9)?;
+        }
+        Ok(())
+    };
+
+    let mut buf = vec![0u8; 64 * 1024];
+    loop {
+        let n = reader.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        for &byte in &buf[..n] {
+            put(&tree, &mut bw, byte as usize)?;
+            tree.update(byte as usize);
+        }
+    }
+    put(&tree, &mut bw, ADAPTIVE_EOF)?;
+    bw.finish()?;
+
     Ok(())
 }
 
-fn fun_bwtmtf(types: &str, num: &str) -> Result<()> {
-    let test_path = "test_files_bwtmtf/".to_string() + types + "/test" + num + ".bwtmtf";
-    let test_path_out = "test_files/".to_string() + types + "/test" + num + ".bmhuf";
-    // let test_path_decode = "test_files_csv/".to_string() + types + "/test" + num + ".dbw";
-    encode(&test_path, &test_path_out)?;
-    println!("Encoded: {types}.{num}");
-    // decode(&test_path_out, &test_path_decode)?;
-    // println!("Decoded");
+fn decode_adaptive_body(reader: impl Read, writer: &mut impl Write) -> Result<()> {
+    let mut br = BitReader::new(reader);
+    let mut tree = AdaptiveTree::new();
+    let mut out = Vec::with_capacity(64 * 1024);
+    loop {
+        let mut node = tree.root();
+        while !tree.is_leaf(node) {
+            node = if br.read_bits(1)? == 0 {
+                tree.left[node]
+            } else {
+                tree.right[node]
+            };
+        }
+
+        let sym = if node == tree.nyt {
+            let sym = br.read_bits(9)? as usize;
+            if sym > ADAPTIVE_EOF || (sym != ADAPTIVE_EOF && tree.leaf[sym] != NONE) {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    "HUF: некоректний новий символ в адаптивному потоці",
+                ));
+            }
+            sym
+        } else {
+            tree.symbol[node]
+        };
+        if sym == ADAPTIVE_EOF {
+            break;
+        }
+
+        out.push(sym as u8);
+        if out.len() == out.capacity() {
+            writer.write_all(&out)?;
+            out.clear();
+        }
+        tree.update(sym);
+    }
+    writer.write_all(&out)?;
+
     Ok(())
 }
 
-fn test_huf(types: &str) -> Result<()> {
-    println!("type of file: {}", types);
-    fun_huf(types, "1")?;
-    fun_huf(types, "2")?;
-    fun_huf(types, "3")?;
-    fun_huf(types, "4")?;
-    fun_huf(types, "5")?;
-    fun_huf(types, "6")?;
-    fun_huf(types, "7")?;
-    fun_huf(types, "8")?;
-    fun_huf(types, "9")?;
-    fun_huf(types, "10")?;
+// Блоковий режим (як у bzip2): вхід ділиться на блоки по BLOCK_SIZE байтів,
+// блок - на групи по GROUP_SIZE символів. Для блока навчається до MAX_TABLES
+// таблиць, кожна група кодується найвигіднішою з них, а номер таблиці
+// (селектор) записується після MTF унарним кодом.
+//
+// Потік: для кожного блока біт 1, довжина блока (32 біти), кількість таблиць
+// (3 біти), довжини кодів кожної таблиці, селектори і самі коди; в кінці біт 0.
+const BLOCK_SIZE: usize = 900_000;
+const GROUP_SIZE: usize = 50;
+const MAX_TABLES: usize = 6;
+const TRAIN_ITERS: usize = 4;
+
+fn tables_count(block_len: usize, used: usize) -> usize {
+    let n = match block_len {
+        0..200 => 2,
+        200..600 => 3,
+        600..1200 => 4,
+        1200..2400 => 5,
+        _ => MAX_TABLES,
+    };
+    n.min(used).max(1)
+}
+
+// Повертає довжини кодів кожної таблиці і селектор для кожної групи.
+fn train_tables(block: &[u8]) -> (Vec<Vec<u8>>, Vec<usize>) {
+    let mut freq = vec![0u64; 256];
+    block.iter().for_each(|&x| freq[x as usize] += 1);
+    let used: Vec<usize> = (0..256).filter(|&i| freq[i] != 0).collect();
+    let n_tables = tables_count(block.len(), used.len());
+
+    // Початкові "довжини": кожна таблиця дешева на своєму діапазоні символів
+    // з приблизно однаковою сумарною частотою.
+    let mut lengths = vec![vec![0u8; 256]; n_tables];
+    let mut remaining = block.len() as u64;
+    let mut sym = used.iter().peekable();
+    for (t, table) in lengths.iter_mut().enumerate() {
+        let target = remaining / (n_tables - t) as u64;
+        let mut taken = 0u64;
+        table.iter_mut().for_each(|l| *l = 15);
+        while let Some(&&s) = sym.peek() {
+            if taken >= target && taken != 0 && t + 1 < n_tables {
+                break;
+            }
+            table[s] = 0;
+            taken += freq[s];
+            sym.next();
+        }
+        remaining -= taken;
+    }
+
+    let mut selectors = vec![0; block.len().div_ceil(GROUP_SIZE)];
+    for _ in 0..TRAIN_ITERS {
+        let mut table_freq = vec![vec![0u64; 256]; n_tables];
+        for (g, group) in block.chunks(GROUP_SIZE).enumerate() {
+            let best = (0..n_tables)
+                .min_by_key(|&t| {
+                    group
+                        .iter()
+                        .map(|&x| lengths[t][x as usize] as u64)
+                        .sum::<u64>()
+                })
+                .unwrap();
+            selectors[g] = best;
+            group
+                .iter()
+                .for_each(|&x| table_freq[best][x as usize] += 1);
+        }
+
+        // Кожна таблиця має вміти закодувати будь-який символ блока.
+        for (t, tf) in table_freq.iter_mut().enumerate() {
+            used.iter().for_each(|&s| tf[s] += 1);
+            lengths[t] = limited_code_lengths(tf, MAX_CODE_LEN);
+        }
+    }
+
+    (lengths, selectors)
+}
+
+pub fn encode_blocks(file_read: &str, file_write: &str) -> Result<()> {
+    let mut reader = File::open(file_read)?;
+    let mut writer = BufWriter::new(File::create(file_write)?);
+    writer.write_all(MAGIC)?;
+    writer.write_all(&[BLOCKS])?;
+    let mut bw = BitWriter::new(writer);
+
+    let mut block = vec![0u8; BLOCK_SIZE];
+    loop {
+        let mut n = 0;
+        while n < BLOCK_SIZE {
+            let read = reader.read(&mut block[n..])?;
+            if read == 0 {
+                break;
+            }
+            n += read;
+        }
+        if n == 0 {
+            break;
+        }
+        let block = &block[..n];
+
+        let (lengths, selectors) = train_tables(block);
+        bw.write_bits(1, 1)?;
+        bw.write_bits(n as u64, 32)?;
+        bw.write_bits(lengths.len() as u64, 3)?;
+        for table in &lengths {
+            write_lengths(|v, k| bw.write_bits(v, k), table)?;
+        }
+
+        let mut order: Vec<usize> = (0..lengths.len()).collect();
+        for &sel in &selectors {
+            let rank = order.iter().position(|&t| t == sel).unwrap();
+            order.remove(rank);
+            order.insert(0, sel);
+            for _ in 0..rank {
+                bw.write_bits(1, 1)?;
+            }
+            bw.write_bits(0, 1)?;
+        }
+
+        let codes: Vec<Vec<(u32, usize)>> = lengths.iter().map(|l| canonical_codes(l)).collect();
+        for (group, &sel) in block.chunks(GROUP_SIZE).zip(&selectors) {
+            for &x in group {
+                let (code, len) = codes[sel][x as usize];
+                bw.write_bits(code as u64, len)?;
+            }
+        }
+    }
+    bw.write_bits(0, 1)?;
+    bw.finish()?;
+
     Ok(())
 }
-fn test_bwt(types: &str) -> Result<()> {
-    println!("type of file: {}", types);
-    fun_bwt(types, "1")?;
-    fun_bwt(types, "2")?;
-    fun_bwt(types, "3")?;
-    fun_bwt(types, "4")?;
-    fun_bwt(types, "5")?;
-    fun_bwt(types, "6")?;
-    fun_bwt(types, "7")?;
-    fun_bwt(types, "8")?;
-    fun_bwt(types, "9")?;
-    fun_bwt(types, "10")?;
+
+fn decode_blocks_body(reader: impl Read, writer: &mut impl Write) -> Result<()> {
+    let mut br = BitReader::new(reader);
+    let mut out = Vec::with_capacity(BLOCK_SIZE);
+    while br.read_bits(1)? == 1 {
+        let n = br.read_bits(32)? as usize;
+        let n_tables = br.read_bits(3)? as usize;
+        if n == 0 || n > BLOCK_SIZE || !(1..=MAX_TABLES).contains(&n_tables) {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "HUF: некоректний заголовок блока",
+            ));
+        }
+        let mut tables = Vec::with_capacity(n_tables);
+        for _ in 0..n_tables {
+            let lengths = read_lengths(|k| Ok(br.read_bits(k)? as u32), 256)?;
+            tables.push(DecodeTable::new(&lengths));
+        }
+
+        let mut order: Vec<usize> = (0..n_tables).collect();
+        let mut selectors = Vec::with_capacity(n.div_ceil(GROUP_SIZE));
+        for _ in 0..n.div_ceil(GROUP_SIZE) {
+            let mut rank = 0;
+            while br.read_bits(1)? == 1 {
+                rank += 1;
+                if rank >= n_tables {
+                    return Err(Error::new(
+                        ErrorKind::InvalidData,
+                        "HUF: некоректний селектор таблиці",
+                    ));
+                }
+            }
+            let sel = order.remove(rank);
+            order.insert(0, sel);
+            selectors.push(sel);
+        }
+
+        out.clear();
+        for (g, &sel) in selectors.iter().enumerate() {
+            let group_len = GROUP_SIZE.min(n - g * GROUP_SIZE);
+            for _ in 0..group_len {
+                out.push(tables[sel].decode_symbol(&mut br)? as u8);
+            }
+        }
+        writer.write_all(&out)?;
+    }
+
     Ok(())
 }
 
-fn test_mtf(types: &str) -> Result<()> {
+pub fn bench_decode(types: &str) -> Result<()> {
     println!("type of file: {}", types);
-    fun_mtf(types, "1")?;
-    fun_mtf(types, "2")?;
-    fun_mtf(types, "3")?;
-    fun_mtf(types, "4")?;
-    fun_mtf(types, "5")?;
-    fun_mtf(types, "6")?;
-    fun_mtf(types, "7")?;
-    fun_mtf(types, "8")?;
-    fun_mtf(types, "9")?;
-    fun_mtf(types, "10")?;
+    for num in 1..=10 {
+        let test_path = format!("test_files/{types}/test{num}.{types}");
+        let test_path_out = format!("test_files/{types}/test{num}.huf");
+        let test_path_decode = format!("test_files/{types}/test{num}.dechuf");
+        encode(&test_path, &test_path_out)?;
+
+        let start = Instant::now();
+        decode_bitwise(&test_path_out, &test_path_decode)?;
+        let bitwise = start.elapsed();
+
+        let start = Instant::now();
+        decode(&test_path_out, &test_path_decode)?;
+        let table = start.elapsed();
+
+        println!(
+            "[{num}], [{:?}], [{:?}], [x{:.1}],",
+            bitwise,
+            table,
+            bitwise.as_secs_f64() / table.as_secs_f64()
+        );
+    }
     Ok(())
 }
 
-fn test_bwtmtf(types: &str) -> Result<()> {
+pub fn order1_gain(types: &str) -> Result<()> {
     println!("type of file: {}", types);
-    fun_bwtmtf(types, "1")?;
-    fun_bwtmtf(types, "2")?;
-    fun_bwtmtf(types, "3")?;
-    fun_bwtmtf(types, "4")?;
-    fun_bwtmtf(types, "5")?;
-    fun_bwtmtf(types, "6")?;
-    fun_bwtmtf(types, "7")?;
-    fun_bwtmtf(types, "8")?;
-    fun_bwtmtf(types, "9")?;
-    fun_bwtmtf(types, "10")?;
+    for num in 1..=10 {
+        let test_path = format!("test_files/{types}/test{num}.{types}");
+        let test_path_order0 = format!("test_files/{types}/test{num}.huf");
+        let test_path_order1 = format!("test_files/{types}/test{num}.huf1");
+        encode(&test_path, &test_path_order0)?;
+        encode_order1(&test_path, &test_path_order1)?;
+
+        let size = std::fs::metadata(&test_path)?.len();
+        let order0 = std::fs::metadata(&test_path_order0)?.len();
+        let order1 = std::fs::metadata(&test_path_order1)?.len();
+        println!(
+            "[{num}], [{}], [{}], [{}], [{:.2}%],",
+            size,
+            order0,
+            order1,
+            100.0 * (order0 as f64 - order1 as f64) / order0 as f64
+        );
+    }
     Ok(())
 }
 
-use rayon::prelude::*;
-use rayon::scope;
+// Вхідні набори звітів: каталог, розширення (порожнє - сам тип файлу) і підпис.
+const REPORT_INPUTS: [(&str, &str, &str); 4] = [
+    ("test_files", "", "huf"),
+    ("test_files_bwt", "bwt", "bwt+huf"),
+    ("test_files_mtf", "mtf", "mtf+huf"),
+    ("test_files_bwtmtf", "bwtmtf", "bwt+mtf+huf"),
+];
 
-fn run_all_tests() -> Result<()> {
-    println!("BWT");
-    let files = ["pdf", "mov", "3mf", "exe", "csv"];
-    files.par_iter().for_each(|f| {
-        test_bwt(f).unwrap(); // всередині відкривається свій файл і пишеться у нього
-    });
+fn all_analysis(types: &str, dir: &str, ext: &str) -> Result<()> {
+    let (mut h, mut l, mut r, mut ratio) = (0.0, 0.0, 0.0, 0.0);
+    for num in 1..=10 {
+        let ext = if ext.is_empty() { types } else { ext };
+        let (freq, _) = build_freq_table(&format!("{dir}/{types}/test{num}.{ext}"))?;
+        let analysis = analyze(&freq, MAX_CODE_LEN)?;
+        if !analysis.kraft_ok() {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("HUF: порушена нерівність Крафта для {dir}/{types}/test{num}.{ext}"),
+            ));
+        }
+        h += analysis.entropy;
+        l += analysis.average_len;
+        r += analysis.redundancy;
+        ratio += 8.0 / analysis.average_len;
+    }
 
-    println!("MTF");
-    files.par_iter().for_each(|f| {
-        test_mtf(f).unwrap(); // всередині відкривається свій файл і пишеться у нього
-    });
-    println!("BWTMTF");
-    files.par_iter().for_each(|f| {
-        test_bwtmtf(f).unwrap(); // всередині відкривається свій файл і пишеться у нього
-    });
+    println!(
+        "[{}], [{:.4}], [{:.4}], [{:.4}], [{:.4}],",
+        types,
+        h / 10.,
+        l / 10.,
+        r / 10.,
+        ratio / 10.
+    );
     Ok(())
 }
 
-fn lens(types: &str, num: &str, algo: &str) -> Result<f32> {
-    let test_path = "test_files/".to_string() + types + "/test" + num + "." + types;
-    let test_path_out = "test_files/".to_string() + types + "/test" + num + "." + algo;
-    let metadata = std::fs::metadata(test_path)?;
-    let size_test = metadata.len();
-    let metadata = std::fs::metadata(test_path_out)?;
-    let arch_test = metadata.len();
-    // println!(
-    //     "[{num}],[{}],[{}],[{}],[{}],",
-    //     size_test,
-    //     arch_test,
-    //     size_test as f32 / (arch_test ) as f32,
-    //     size_test as f32 / (arch_test - 1028) as f32
-    // );
-
-    Ok(size_test as f32 / (arch_test - 1028) as f32)
-}
-
-fn calc(s: &mut f32, max_t: &mut f32, min_t: &mut f32, t: f32) {
-    *max_t = max_t.max(t);
-    *min_t = min_t.min(t);
-    *s += t;
-}
-
-fn all_lens(types: &str, algo: &str,cap: &str) {
-    // println!("{}.{}", types, algo);
-    let mut s = 0.0;
-    let mut min_t: f32 = 1000.;
-    let mut max_t: f32 = -1000.;
-
-    // println!("#figure(\n table(\n columns: 5,\n table.header[*Файл*][*Початкова \\#байт*][*Cтиснута \\#байт*][*коеф*][*коеф* з мета], ");
-    let mut t = lens(types, "1", algo).unwrap();
-    calc(&mut s, &mut max_t, &mut min_t, t);
-    t = lens(types, "2", algo).unwrap();
-    calc(&mut s, &mut max_t, &mut min_t, t);
-    t = lens(types, "3", algo).unwrap();
-    calc(&mut s, &mut max_t, &mut min_t, t);
-    t = lens(types, "4", algo).unwrap();
-    calc(&mut s, &mut max_t, &mut min_t, t);
-    t = lens(types, "5", algo).unwrap();
-    calc(&mut s, &mut max_t, &mut min_t, t);
-    t = lens(types, "6", algo).unwrap();
-    calc(&mut s, &mut max_t, &mut min_t, t);
-    t = lens(types, "7", algo).unwrap();
-    calc(&mut s, &mut max_t, &mut min_t, t);
-    t = lens(types, "8", algo).unwrap();
-    calc(&mut s, &mut max_t, &mut min_t, t);
-    t = lens(types, "9", algo).unwrap();
-    calc(&mut s, &mut max_t, &mut min_t, t);
-    t = lens(types, "10", algo).unwrap();
-    calc(&mut s, &mut max_t, &mut min_t, t);
-    // println!("),\n caption: \"{types} {cap}\",\n)\n");
-    
-    println!("[{}],  [{}], [{}],[{}],", types, s / 10., min_t, max_t,);
-
-
-    // println!(
-    //     "{}.{}: max[{}], min[{}], ave[{}]]",
-    //     types,
-    //     algo,
-    //     max_t,
-    //     min_t,
-    //     s / 10.0
-    // );
-}
-
-fn main() -> Result<()> {
-    // println!("HUF");
-
-    // run_all_tests();
-    // println!("BWT");
-    let files = ["pdf", "mov", "3mf", "exe", "csv"];
-    // files.par_iter().for_each(|f| {
-    //     test_huf(f).unwrap(); // всередині відкривається свій файл і пишеться у нього
-    // });
-
-
-     println!("== HUF");
-    // println!("---- START MOV ----");
-    println!("=== MOV");
-    all_lens("mov", "lzw", "");
-    all_lens("mov", "blzw", "bwt");
-    all_lens("mov", "mlzw", "mtf");
-    all_lens("mov", "bmlzw", "bwt+mtf");
-
-    // println!("---- END MOV ----\n");
-    // println!("---- START PDF ----");
-    println!("=== PDF");
-    all_lens("pdf", "lzw", "");
-    all_lens("pdf", "blzw", "bwt");
-    all_lens("pdf", "mlzw", "mtf");
-    all_lens("pdf", "bmlzw", "bwt+mtf");
-    // println!("---- END PDF ----\n");
-    // println!("---- START CSV ----");
-    println!("=== CSV");
-    all_lens("csv", "lzw", "");
-    all_lens("csv", "blzw", "bwt");
-    all_lens("csv", "mlzw", "mtf");
-    all_lens("csv", "bmlzw", "bwt+mtf");
-
-    // println!("---- END CSV ----\n");
-    // println!("---- START EXE ----");
-
-    println!("=== EXE");
-    all_lens("exe", "lzw", "");
-    all_lens("exe", "blzw", "bwt");
-    all_lens("exe", "mlzw", "mtf");
-    all_lens("exe", "bmlzw", "bwt+mtf");
-    // println!("---- END EXE ----\n");
-    // println!("---- START 3mf ----");
-
-    println!("=== 3MF");
-    all_lens("3mf", "lzw", "");
-    all_lens("3mf", "blzw", "bwt");
-    all_lens("3mf", "mlzw", "mtf");
-    all_lens("3mf", "bmlzw", "bwt+mtf");
-
-
-
-    println!("#figure(\n table(\n columns: 4,\n table.header[*Тип файлу*][*Середній коеф*][*Мінімальний*][*Максимальний*], ");
-    all_lens("mov", "huf", "");
-    all_lens("pdf", "huf", "");
-    all_lens("csv", "huf", "");
-    all_lens("exe", "huf", "");
-    all_lens("3mf", "huf", "");
-    println!("),\n caption: \"huf\",\n)\n");
-
-    println!("#figure(\n table(\n columns: 4,\n table.header[*Тип файлу*][*Середній коеф*][*Мінімальний*][*Максимальний*], ");
-    all_lens("mov", "bhuf", "bwt");
-    all_lens("pdf", "bhuf", "bwt");
-    all_lens("csv", "bhuf", "bwt");
-    all_lens("exe", "bhuf", "bwt");
-    all_lens("3mf", "bhuf", "bwt");
-    println!("),\n caption: \"bwt+huf\",\n)\n");
-    println!("#figure(\n table(\n columns: 4,\n table.header[*Тип файлу*][*Середній коеф*][*Мінімальний*][*Максимальний*], ");
-    all_lens("mov", "mhuf", "mtf");
-    all_lens("pdf", "mhuf", "mtf");
-    all_lens("csv", "mhuf", "mtf");
-    all_lens("exe", "mhuf", "mtf");
-    all_lens("3mf", "mhuf", "mtf");
-    println!("),\n caption: \"mtf+huf\",\n)\n");
-    println!("#figure(\n table(\n columns: 4,\n table.header[*Тип файлу*][*Середній коеф*][*Мінімальний*][*Максимальний*], ");
-    all_lens("mov", "bmhuf", "bwt+mtf");
-    all_lens("pdf", "bmhuf", "bwt+mtf");
-    all_lens("csv", "bmhuf", "bwt+mtf");
-    all_lens("exe", "bmhuf", "bwt+mtf");
-    all_lens("3mf", "bmhuf", "bwt+mtf");
-    println!("),\n caption: \" bwt+mtf+huf\",\n)\n");
+// Відношення розміру вихідного файлу до розміру, який дав би encode; сам файл
+// не кодується.
+fn all_sizes(types: &str, dir: &str, ext: &str) -> Result<()> {
+    let (mut s, mut min_t, mut max_t) = (0.0, f64::MAX, 0.0f64);
+    for num in 1..=10 {
+        let ext = if ext.is_empty() { types } else { ext };
+        let size = std::fs::metadata(format!("test_files/{types}/test{num}.{types}"))?.len();
+        let (freq, _) = build_freq_table(&format!("{dir}/{types}/test{num}.{ext}"))?;
+        let t = size as f64 / analyze(&freq, MAX_CODE_LEN)?.encoded_size() as f64;
+        max_t = max_t.max(t);
+        min_t = min_t.min(t);
+        s += t;
+    }
+    println!("[{}], [{:.3}], [{:.3}], [{:.3}],", types, s / 10., min_t, max_t);
+    Ok(())
+}
 
+// Таблиці коефіцієнтів стиснення для звіту: Хаффман на вихідних файлах і
+// після BWT, MTF та BWT+MTF.
+pub fn report_sizes(types: &[&str]) -> Result<()> {
+    for (dir, ext, caption) in REPORT_INPUTS {
+        println!("#figure(\n table(\n columns: 4,\n table.header[*Тип файлу*][*Середній коеф*][*Мінімальний*][*Максимальний*], ");
+        for types in types {
+            all_sizes(types, dir, ext)?;
+        }
+        println!("),\n caption: \"{caption}\",\n)\n");
+    }
     Ok(())
 }
+
+// Таблиці ентропії, середньої довжини коду і надлишковості для звіту: на
+// вихідних файлах і після BWT, MTF та BWT+MTF.
+pub fn report_analysis(types: &[&str]) -> Result<()> {
+    for (dir, ext, caption) in REPORT_INPUTS {
+        println!("#figure(\n table(\n columns: 5,\n table.header[*Тип файлу*][*Ентропія*][*Середня довжина*][*Надлишковість*][*Коеф*], ");
+        for types in types {
+            all_analysis(types, dir, ext)?;
+        }
+        println!("),\n caption: \"{caption}\",\n)\n");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp(name: &str) -> String {
+        let path = std::env::temp_dir().join(format!("huf-test-{}-{name}", std::process::id()));
+        path.to_str().unwrap().to_string()
+    }
+
+    // Кодує data у файл версії 3 і повертає стиснуті байти та розкодоване.
+    fn round_trip(name: &str, data: &[u8]) -> (Vec<u8>, Vec<u8>) {
+        let input = temp(name);
+        let packed = temp(&format!("{name}.huf"));
+        let output = temp(&format!("{name}.out"));
+        std::fs::write(&input, data).unwrap();
+        encode(&input, &packed).unwrap();
+        decode(&packed, &output).unwrap();
+        let result = (std::fs::read(&packed).unwrap(), std::fs::read(&output).unwrap());
+        for path in [input, packed, output] {
+            std::fs::remove_file(path).unwrap();
+        }
+        result
+    }
+
+    fn decode_bytes(name: &str, packed: &[u8]) -> Result<Vec<u8>> {
+        let (input, output) = (temp(name), temp(&format!("{name}.out")));
+        std::fs::write(&input, packed).unwrap();
+        let result = decode(&input, &output).and_then(|_| std::fs::read(&output));
+        let _ = std::fs::remove_file(input);
+        let _ = std::fs::remove_file(output);
+        result
+    }
+
+    fn legacy_stream(freq: &[u32; 256], count: u32) -> Vec<u8> {
+        let mut data: Vec<u8> = freq.iter().flat_map(|x| x.to_le_bytes()).collect();
+        data.extend(count.to_le_bytes());
+        data
+    }
+
+    #[test]
+    fn empty_input() {
+        assert_eq!(build_tree(&[0; 256]).len(), 256);
+        assert!(limited_code_lengths(&[0; 256], MAX_CODE_LEN).iter().all(|&l| l == 0));
+
+        let (packed, unpacked) = round_trip("empty", b"");
+        assert!(unpacked.is_empty());
+        // заголовок, лічильник і 16 бітів карти груп
+        assert_eq!(packed.len(), 4 + 8 + 2);
+    }
+
+    #[test]
+    fn single_symbol() {
+        let mut freq = [0u64; 256];
+        freq[b'x' as usize] = 1000;
+        let lengths = limited_code_lengths(&freq, MAX_CODE_LEN);
+        assert_eq!(lengths[b'x' as usize], 1);
+        assert_eq!(lengths.iter().filter(|&&l| l != 0).count(), 1);
+
+        for n in [1, 7, 8, 1000] {
+            let data = vec![b'x'; n];
+            let (packed, unpacked) = round_trip(&format!("single{n}"), &data);
+            assert!(unpacked == data);
+            // по біту на символ, а не нуль бітів
+            assert!(packed.len() >= 12 + n.div_ceil(8));
+        }
+    }
+
+    #[test]
+    fn counts_above_u32() {
+        let big = u32::MAX as u64;
+        let mut freq = [0u64; 256];
+        freq[0] = 5 * big;
+        freq[1] = 3 * big;
+        freq[2] = big + 1;
+        freq[3] = 1;
+        let tree = build_tree(&freq);
+        assert_eq!(tree.last().unwrap().freq, 9 * big + 2);
+        let lengths = limited_code_lengths(&freq, MAX_CODE_LEN);
+        assert_eq!(&lengths[..4], &[1, 2, 3, 3]);
+
+        // довжини проходять через запис і читання таблиці в заголовку
+        let mut bw = BitWriter::new(Vec::new());
+        write_lengths(|v, k| bw.write_bits(v, k), &lengths).unwrap();
+        let table = bw.finish().unwrap();
+        let mut br = BitReader::new(&table[..]);
+        let read = read_lengths(|k| Ok(br.read_bits(k)? as u32), 256).unwrap();
+        assert_eq!(read, lengths);
+
+        // лічильник байтів у заголовку u64: 2^32 + 5 не обрізається до 5,
+        // тож п'яти закодованих байтів декодеру замало
+        let (mut packed, _) = round_trip("count", b"aaaab");
+        assert_eq!(decode_bytes("count-ok", &packed).unwrap(), b"aaaab");
+        packed[4..12].copy_from_slice(&((1u64 << 32) + 5).to_le_bytes());
+        assert!(decode_bytes("count-big", &packed).is_err());
+    }
+
+    #[test]
+    fn analysis_code_lengths() {
+        // частоти Фібоначчі без обмеження дали б коди довжиною 39 біт
+        let mut freq = vec![1u64, 1];
+        while freq.len() < 40 {
+            freq.push(freq[freq.len() - 1] + freq[freq.len() - 2]);
+        }
+        let analysis = analyze(&freq, 32).unwrap();
+        assert!(analysis.kraft_ok());
+        assert_eq!(analysis.lengths.iter().copied().max(), Some(32));
+        assert!(matches!(analyze(&freq, 33), Err(e) if e.kind() == ErrorKind::InvalidInput));
+        assert!(matches!(analyze(&freq, 64), Err(e) if e.kind() == ErrorKind::InvalidInput));
+    }
+
+    // encoded_size збігається з розміром файлу від encode, тож report_sizes
+    // може не кодувати файли
+    #[test]
+    fn analysis_encoded_size() {
+        let text: Vec<u8> = (0..20_000u32).map(|i| b"abcdefgh"[(i * i % 7) as usize]).collect();
+        let all: Vec<u8> = (0..=255u8).cycle().take(3001).collect();
+        let inputs: [(&str, &[u8]); 5] =
+            [("empty", b""), ("one", b"q"), ("single", &[b'x'; 1001]), ("text", &text), ("all", &all)];
+        for (name, data) in inputs {
+            let mut freq = vec![0u64; 256];
+            data.iter().for_each(|&b| freq[b as usize] += 1);
+            let analysis = analyze(&freq, MAX_CODE_LEN).unwrap();
+            let (packed, _) = round_trip(&format!("size-{name}"), data);
+            assert_eq!(analysis.encoded_size(), packed.len() as u64, "{name}");
+        }
+    }
+
+    // три однобітні коди: таблиця декодера на таких довжинах вийшла б за межі
+    #[test]
+    fn oversubscribed_lengths() {
+        let mut lengths = [0u8; 256];
+        lengths[..3].fill(1);
+        let mut packed = MAGIC.to_vec();
+        packed.push(VERSION);
+        packed.extend(3u64.to_le_bytes());
+        let mut bw = BitWriter::new(packed);
+        write_lengths(|v, k| bw.write_bits(v, k), &lengths).unwrap();
+        bw.write_bits(0, 8).unwrap();
+        let packed = bw.finish().unwrap();
+
+        let err = decode_bytes("oversubscribed", &packed).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+    }
+
+    fn order1_round_trip(name: &str, data: &[u8]) -> Vec<u8> {
+        let (input, packed) = (temp(name), temp(&format!("{name}.huf")));
+        std::fs::write(&input, data).unwrap();
+        encode_order1(&input, &packed).unwrap();
+        let result = std::fs::read(&packed).unwrap();
+        std::fs::remove_file(input).unwrap();
+        std::fs::remove_file(packed).unwrap();
+        assert!(decode_bytes(&format!("{name}-dec"), &result).unwrap() == data, "{name}");
+        result
+    }
+
+    fn context_freq(data: &[u8]) -> Vec<Vec<u64>> {
+        let mut ctx_freq = vec![vec![0u64; 256]; 256];
+        let mut prev = 0;
+        for &x in data {
+            ctx_freq[prev][x as usize] += 1;
+            prev = x as usize;
+        }
+        ctx_freq
+    }
+
+    #[test]
+    fn order1_streams() {
+        let mut x = 9u32;
+        let mut next = move || {
+            x = x.wrapping_mul(1103515245).wrapping_add(12345);
+            (x >> 16) as u8
+        };
+        order1_round_trip("order1-empty", b"");
+        order1_round_trip("order1-one", b"q");
+        order1_round_trip("order1-single", &[b'x'; 5000]);
+
+        // власну таблицю отримує лише контекст 'Z': за ним завжди йде 'z'
+        let mut one = Vec::new();
+        for _ in 0..60_000 {
+            let c = if one.last() == Some(&b'Z') { b'z' } else { next() };
+            one.push(c);
+        }
+        let (map, tables) = order1_tables(&context_freq(&one));
+        assert_eq!(map.iter().filter(|&&t| t != 0).count(), 1);
+        assert_ne!(map[b'Z' as usize], 0);
+        assert_eq!(tables.len(), 2);
+        order1_round_trip("order1-ctx", &one);
+
+        // за кожним байтом b йде b + 1 або b + 3: таблиця в кожного контексту
+        let mut all = vec![0u8];
+        for _ in 0..100_000 {
+            let prev = *all.last().unwrap();
+            all.push(prev.wrapping_add(1 + next() % 2 * 2));
+        }
+        let (map, _) = order1_tables(&context_freq(&all));
+        assert!(map.iter().all(|&t| t != 0));
+        let packed = order1_round_trip("order1-all", &all);
+        assert!(packed.len() < all.len() / 6);
+
+        // обірваний потік
+        let packed = order1_round_trip("order1-cut", &all[..5000]);
+        for n in (4..packed.len()).step_by(101) {
+            assert!(decode_bytes("order1-cut-dec", &packed[..n]).is_err(), "обрізано до {n}");
+        }
+
+        // карта контекстів посилається на таблицю з недопустимими довжинами, і
+        // на порожню таблицю, у якій немає символа з потоку
+        let mut over = [0u8; 256];
+        over[..3].fill(1);
+        let mut only_a = [0u8; 256];
+        only_a[b'a' as usize] = 1;
+        let cases: [(&[u8; 256], &[u8; 256], &str); 2] =
+            [(&over, &over, "over"), (&only_a, &[0; 256], "empty")];
+        for (shared, own, name) in cases {
+            let mut packed = MAGIC.to_vec();
+            packed.push(ORDER1);
+            packed.extend(2u64.to_le_bytes());
+            let mut bw = BitWriter::new(packed);
+            // власна таблиця лише в контексту 'a'
+            for ctx in 0..256 {
+                bw.write_bits((ctx == b'a' as usize) as u64, 1).unwrap();
+            }
+            write_lengths(|v, k| bw.write_bits(v, k), shared).unwrap();
+            write_lengths(|v, k| bw.write_bits(v, k), own).unwrap();
+            bw.write_bits(0, 16).unwrap();
+            let err = decode_bytes(&format!("order1-{name}"), &bw.finish().unwrap()).unwrap_err();
+            assert_eq!(err.kind(), ErrorKind::InvalidData, "{name}");
+        }
+    }
+
+    // Властивість сусідства FGK: ваги не спадають за номером вузла, а вага
+    // внутрішнього вузла дорівнює сумі ваг дітей.
+    fn check_sibling_property(tree: &AdaptiveTree) {
+        assert!(tree.weight.windows(2).all(|w| w[0] <= w[1]));
+        for node in tree.nyt..tree.weight.len() {
+            if !tree.is_leaf(node) {
+                let (l, r) = (tree.left[node], tree.right[node]);
+                assert_eq!(tree.weight[node], tree.weight[l] + tree.weight[r]);
+                assert_eq!((tree.parent[l], tree.parent[r]), (node, node));
+            }
+        }
+    }
+
+    #[test]
+    fn adaptive_streams() {
+        let mut x = 3u32;
+        // символи k і k + 100 з імовірністю 2^-(k+2): коди до двох десятків бітів
+        let skewed: Vec<u8> = (0..300_000)
+            .map(|_| {
+                x = x.wrapping_mul(1103515245).wrapping_add(12345);
+                ((x >> 8) | 1 << 22).trailing_zeros() as u8 + (x >> 31) as u8 * 100
+            })
+            .collect();
+        let all: Vec<u8> = (0..=255u8).chain((0..=255u8).rev()).cycle().take(5000).collect();
+        let inputs: [(&str, &[u8]); 5] = [
+            ("empty", b""),
+            ("one", b"x"),
+            ("single", &[b'x'; 10_000]),
+            ("all", &all),
+            ("skewed", &skewed),
+        ];
+        for (name, data) in inputs {
+            let mut packed = Vec::new();
+            encode_adaptive_stream(data, &mut packed).unwrap();
+            // decode розпізнає формат за байтом версії
+            assert!(decode_bytes(&format!("adaptive-{name}"), &packed).unwrap() == data, "{name}");
+
+            let mut tree = AdaptiveTree::new();
+            data.iter().for_each(|&b| tree.update(b as usize));
+            check_sibling_property(&tree);
+            assert_eq!(tree.weight[tree.root()], data.len() as u64);
+        }
+
+        // один символ: після першої появи - по біту на символ
+        let mut packed = Vec::new();
+        encode_adaptive_stream(&[b'x'; 10_000][..], &mut packed).unwrap();
+        assert!(packed.len() <= 4 + 10_000usize.div_ceil(8) + 4);
+        // усі 256 символів приходять через NYT, а дерево не переповнюється
+        let mut tree = AdaptiveTree::new();
+        (0..ADAPTIVE_SYMBOLS - 1).for_each(|b| tree.update(b));
+        assert_eq!(tree.nyt, 0);
+        assert!(tree.leaf[..256].iter().all(|&node| node != NONE));
+    }
+
+    fn encode_blocks_bytes(name: &str, data: &[u8]) -> Vec<u8> {
+        let (input, packed) = (temp(name), temp(&format!("{name}.huf")));
+        std::fs::write(&input, data).unwrap();
+        encode_blocks(&input, &packed).unwrap();
+        let result = std::fs::read(&packed).unwrap();
+        std::fs::remove_file(input).unwrap();
+        std::fs::remove_file(packed).unwrap();
+        result
+    }
+
+    #[test]
+    fn block_streams() {
+        let mut x = 5u32;
+        let mut next = move || {
+            x = x.wrapping_mul(1103515245).wrapping_add(12345);
+            x >> 8
+        };
+        // ділянки з різними алфавітами: групам потрібні різні таблиці
+        let skewed: Vec<u8> = (0..20_000)
+            .map(|i| if i / 1000 % 2 == 0 { b'a' + (next() % 4) as u8 } else { (next() % 256) as u8 })
+            .collect();
+        let (_, selectors) = train_tables(&skewed);
+        assert!(selectors.iter().any(|&s| s != selectors[0]), "таблиці не змінюються");
+
+        let big: Vec<u8> = (0..BLOCK_SIZE + 1).map(|_| (next() % 7 * next() % 5) as u8).collect();
+        let inputs: [(&str, &[u8]); 5] = [
+            ("empty", b""),
+            ("one", b"q"),
+            ("exact", &big[..BLOCK_SIZE]),
+            ("over", &big),
+            ("skewed", &skewed),
+        ];
+        for (name, data) in inputs {
+            let packed = encode_blocks_bytes(&format!("blocks-{name}"), data);
+            let unpacked = decode_bytes(&format!("blocks-{name}-dec"), &packed).unwrap();
+            assert!(unpacked == data, "{name}");
+        }
+
+        // обірваний потік
+        let packed = encode_blocks_bytes("blocks-cut", &skewed[..3000]);
+        for n in (5..packed.len()).step_by(97) {
+            assert!(decode_bytes("blocks-cut-dec", &packed[..n]).is_err(), "обрізано до {n}");
+        }
+        // довжина блока 0 або більша за BLOCK_SIZE
+        for n in [0, BLOCK_SIZE as u64 + 1, u32::MAX as u64] {
+            let mut bw = BitWriter::new(MAGIC.iter().chain(&[BLOCKS]).copied().collect::<Vec<u8>>());
+            bw.write_bits(1, 1).unwrap();
+            bw.write_bits(n, 32).unwrap();
+            bw.write_bits(1, 3).unwrap();
+            let err = decode_bytes("blocks-n", &bw.finish().unwrap()).unwrap_err();
+            assert_eq!(err.kind(), ErrorKind::InvalidData, "n = {n}");
+        }
+    }
+
+    // bench_decode порівнює швидкість, тож обидва декодери мають давати те саме,
+    // зокрема на кодах довших за первинну таблицю.
+    #[test]
+    fn bitwise_decoder() {
+        let mut data: Vec<u8> = (0..=255u8)
+            .flat_map(|b| std::iter::repeat_n(b, (1 << 14) >> b.min(14)))
+            .collect();
+        let mut x = 1u32;
+        for i in (1..data.len()).rev() {
+            x = x.wrapping_mul(1103515245).wrapping_add(12345);
+            data.swap(i, (x >> 8) as usize % (i + 1));
+        }
+        let mut freq = vec![0u64; 256];
+        data.iter().for_each(|&b| freq[b as usize] += 1);
+        assert_eq!(limited_code_lengths(&freq, MAX_CODE_LEN).iter().max(), Some(&15));
+
+        let (input, packed, output) = (temp("bitwise"), temp("bitwise.huf"), temp("bitwise.out"));
+        std::fs::write(&input, &data).unwrap();
+        encode(&input, &packed).unwrap();
+        decode_bitwise(&packed, &output).unwrap();
+        let bitwise = std::fs::read(&output).unwrap();
+        for path in [input, packed, output] {
+            std::fs::remove_file(path).unwrap();
+        }
+        assert!(bitwise == data);
+        assert!(round_trip("bitwise-table", &data).1 == data);
+    }
+
+    #[test]
+    fn legacy_streams() {
+        // старий формат: єдиний символ мав код нульової довжини і жодного біта
+        let mut freq = [0u32; 256];
+        freq[b'z' as usize] = 7;
+        let one = decode_bytes("legacy-one", &legacy_stream(&freq, 7)).unwrap();
+        assert_eq!(one, b"zzzzzzz");
+
+        let empty = decode_bytes("legacy-empty", &legacy_stream(&[0; 256], 0)).unwrap();
+        assert!(empty.is_empty());
+    }
+
+    #[test]
+    fn symbol_alphabets() {
+        let symbols = [0u32, 5, (MAX_ALPHABET - 1) as u32, 5];
+        let packed = encode_symbols(&symbols, MAX_ALPHABET).unwrap();
+        assert_eq!(decode_symbols::<u32>(&packed).unwrap(), symbols);
+
+        for alphabet in [0, MAX_ALPHABET + 1] {
+            let err = encode_symbols(&symbols[..1], alphabet).unwrap_err();
+            assert_eq!(err.kind(), ErrorKind::InvalidInput);
+        }
+        // заголовок з величезним алфавітом відкидається до виділення пам'яті
+        for alphabet in [0, MAX_ALPHABET as u32 + 1, u32::MAX] {
+            let mut bad = packed.clone();
+            bad[4..8].copy_from_slice(&alphabet.to_le_bytes());
+            let err = decode_symbols::<u32>(&bad).unwrap_err();
+            assert_eq!(err.kind(), ErrorKind::InvalidData);
+        }
+    }
+
+    #[test]
+    fn chunked_streams() {
+        let input = temp("chunked");
+        let packed = temp("chunked.huf");
+        let text: Vec<u8> = (0..5000u32).map(|i| b"abcdefgh"[(i * i % 7) as usize]).collect();
+        for (data, chunk) in [(&text[..], 7), (&text[..], 1 << 20), (&b"zzzzzzz"[..], 3), (&b""[..], 5)] {
+            std::fs::write(&input, data).unwrap();
+            encode_chunked(&input, &packed, chunk).unwrap();
+            let packed = std::fs::read(&packed).unwrap();
+            assert!(decode_bytes("chunked-dec", &packed).unwrap() == data, "шматок {chunk}");
+        }
+
+        // розмір шматка обмежений і в кодері, і в заголовку
+        for chunk in [0, MAX_CHUNK + 1] {
+            let err = encode_chunked(&input, &packed, chunk).unwrap_err();
+            assert_eq!(err.kind(), ErrorKind::InvalidInput);
+        }
+        encode_chunked(&input, &packed, 5).unwrap();
+        let mut data = std::fs::read(&packed).unwrap();
+        data[12..16].copy_from_slice(&(MAX_CHUNK as u32 + 1).to_le_bytes());
+        let err = decode_bytes("chunked-big", &data).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+
+        // довжина шматка більша, ніж можуть дати його байти
+        std::fs::write(&input, &text).unwrap();
+        encode_chunked(&input, &packed, 7).unwrap();
+        let mut data = std::fs::read(&packed).unwrap();
+        let lengths_len = u32::from_le_bytes(data[16..20].try_into().unwrap()) as usize;
+        let part = 20 + lengths_len;
+        data[part..part + 4].copy_from_slice(&u32::MAX.to_le_bytes());
+        let err = decode_bytes("chunked-part", &data).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+
+        std::fs::remove_file(input).unwrap();
+        std::fs::remove_file(packed).unwrap();
+    }
+}
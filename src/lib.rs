@@ -268,3 +268,131 @@ impl BitStream {
         Ok(())
     }
 }
+
+// Читач бітів з тим самим порядком, що й BitStream (молодший біт байта першим),
+// але з можливістю подивитись наступні біти без зсуву позиції.
+pub struct BitReader<R> {
+    reader: R,
+    buffer: Vec<u8>,
+    index_buf: usize,
+    bits: u64,
+    count: usize,
+}
+
+impl<R: Read> BitReader<R> {
+    // Найбільша кількість бітів, яку можна переглянути за раз.
+    pub const MAX_PEEK: usize = 56;
+
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            buffer: Vec::with_capacity(BUFFER_SIZE),
+            index_buf: 0,
+            bits: 0,
+            count: 0,
+        }
+    }
+
+    fn refill(&mut self) -> io::Result<()> {
+        while self.count <= Self::MAX_PEEK {
+            if self.index_buf == self.buffer.len() {
+                self.buffer.resize(BUFFER_SIZE, 0);
+                let n = self.reader.read(&mut self.buffer)?;
+                self.buffer.truncate(n);
+                self.index_buf = 0;
+                if n == 0 {
+                    break;
+                }
+            }
+            self.bits |= (self.buffer[self.index_buf] as u64) << self.count;
+            self.index_buf += 1;
+            self.count += 8;
+        }
+        Ok(())
+    }
+
+    // Наступні bit_len бітів; після кінця файла доповнюються нулями.
+    pub fn peek(&mut self, bit_len: usize) -> io::Result<u64> {
+        debug_assert!(bit_len <= Self::MAX_PEEK);
+        if self.count < bit_len {
+            self.refill()?;
+        }
+        Ok(self.bits & ((1u64 << bit_len) - 1))
+    }
+
+    pub fn consume(&mut self, bit_len: usize) -> io::Result<()> {
+        if self.count < bit_len {
+            self.refill()?;
+        }
+        if self.count < bit_len {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "Задана довжина послідовності не відповідає обсягу даних, що залишилися у файлі.",
+            ));
+        }
+        self.bits >>= bit_len;
+        self.count -= bit_len;
+        Ok(())
+    }
+
+    pub fn read_bits(&mut self, bit_len: usize) -> io::Result<u64> {
+        let value = self.peek(bit_len)?;
+        self.consume(bit_len)?;
+        Ok(value)
+    }
+}
+
+// Запис бітів у будь-який Write в порядку BitStream: перший біт - молодший.
+pub struct BitWriter<W: Write> {
+    writer: W,
+    buffer: Vec<u8>,
+    bits: u64,
+    count: usize,
+}
+
+impl<W: Write> BitWriter<W> {
+    pub const MAX_WRITE: usize = 56;
+
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer,
+            buffer: Vec::with_capacity(BUFFER_SIZE),
+            bits: 0,
+            count: 0,
+        }
+    }
+
+    // Біти накопичуються до 32 і скидаються в буфер одним шматком; між
+    // викликами в bits лишається менше 32 бітів.
+    pub fn write_bits(&mut self, value: u64, bit_len: usize) -> io::Result<()> {
+        debug_assert!(bit_len <= Self::MAX_WRITE);
+        if bit_len > 32 {
+            self.write_bits(value, 32)?;
+            return self.write_bits(value >> 32, bit_len - 32);
+        }
+        self.bits |= (value & ((1u64 << bit_len) - 1)) << self.count;
+        self.count += bit_len;
+        if self.count >= 32 {
+            self.buffer.extend_from_slice(&(self.bits as u32).to_le_bytes());
+            self.bits >>= 32;
+            self.count -= 32;
+            if self.buffer.len() >= BUFFER_SIZE {
+                self.writer.write_all(&self.buffer)?;
+                self.buffer.clear();
+            }
+        }
+        Ok(())
+    }
+
+    // Дописує неповні байти (доповнені нулями) і повертає внутрішній Write.
+    pub fn finish(mut self) -> io::Result<W> {
+        let bytes = self.count.div_ceil(8);
+        self.buffer.extend_from_slice(&self.bits.to_le_bytes()[..bytes]);
+        self.bits = 0;
+        self.count = 0;
+        self.writer.write_all(&self.buffer)?;
+        self.buffer.clear();
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
@@ -0,0 +1,205 @@
+use bs::{BitReader, BitWriter};
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Error, ErrorKind, Read, Result, Seek, SeekFrom, Write};
+
+// Класичний LZ78: пари (номер фрази, наступний байт). Фраза 0 - порожня,
+// кожна пара додає нову фразу "фраза + байт". Номер пишеться найменшою
+// кількістю бітів, якою можна записати номери 0..size, тож ширина росте разом
+// зі словником. Коли фраз стає 2^max_bits, словник починається заново.
+// Файл: "L78", версія, max_bits, u64 кількість байтів, далі бітовий потік.
+// Остання пара може бути без байта, якщо вхід закінчився посеред фрази.
+const MAGIC: &[u8; 3] = b"L78";
+const VERSION: u8 = 1;
+const MAX_BITS: usize = 24;
+const DEFAULT_MAX_BITS: usize = 21;
+
+// Кількість бітів для номерів 0..size.
+fn index_bits(size: u32) -> usize {
+    (u32::BITS - (size - 1).leading_zeros()) as usize
+}
+
+pub fn encode(file_read: &str, file_write: &str) -> Result<()> {
+    encode_with(file_read, file_write, DEFAULT_MAX_BITS)
+}
+
+pub fn encode_with(file_read: &str, file_write: &str, max_bits: usize) -> Result<()> {
+    if !(1..=MAX_BITS).contains(&max_bits) {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!("LZ78: ширина номера має бути в межах 1..={MAX_BITS} біт"),
+        ));
+    }
+    let reader = BufReader::new(File::open(file_read)?);
+
+    // кількість байтів дописується в заголовок наприкінці: розмір із metadata
+    // не збігається з прочитаним для каналів і файлів, що змінюються
+    let mut writer = BufWriter::new(File::create(file_write)?);
+    writer.write_all(MAGIC)?;
+    writer.write_all(&[VERSION, max_bits as u8])?;
+    writer.write_all(&0u64.to_le_bytes())?;
+    let mut ds = BitWriter::new(writer);
+
+    let full = 1u32 << max_bits;
+    // (фраза, байт) -> номер нової фрази
+    let mut dict: HashMap<(u32, u8), u32> = HashMap::new();
+    let mut size = 1u32;
+    let mut phrase = 0u32;
+    let mut total = 0u64;
+    for c in reader.bytes() {
+        let c = c?;
+        total += 1;
+        if let Some(&next) = dict.get(&(phrase, c)) {
+            phrase = next;
+            continue;
+        }
+
+        ds.write_bits(phrase as u64, index_bits(size))?;
+        ds.write_bits(c as u64, 8)?;
+        if size == full {
+            dict.clear();
+            size = 1;
+        } else {
+            dict.insert((phrase, c), size);
+            size += 1;
+        }
+        phrase = 0;
+    }
+    if phrase != 0 {
+        ds.write_bits(phrase as u64, index_bits(size))?;
+    }
+
+    let mut writer = ds.finish()?.into_inner().map_err(|e| e.into_error())?;
+    writer.seek(SeekFrom::Start(5))?;
+    writer.write_all(&total.to_le_bytes())
+}
+
+pub fn decode(file_read: &str, file_write: &str) -> Result<()> {
+    let mut reader = BufReader::new(File::open(file_read)?);
+    let mut header = [0u8; 13];
+    reader.read_exact(&mut header)?;
+    let max_bits = header[4] as usize;
+    if &header[..3] != MAGIC || header[3] != VERSION || !(1..=MAX_BITS).contains(&max_bits) {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            "LZ78: невідомий формат або версія",
+        ));
+    }
+    let total = u64::from_le_bytes(header[5..].try_into().unwrap());
+    let mut ds = BitReader::new(reader);
+    let mut writer = BufWriter::new(File::create(file_write)?);
+
+    let full = 1u32 << max_bits;
+    // (батьківська фраза, останній байт); фраза 0 порожня
+    let mut phrases: Vec<(u32, u8)> = vec![(0, 0)];
+    let mut word = Vec::new();
+    let mut written = 0u64;
+    while written < total {
+        let size = phrases.len() as u32;
+        let index = ds.read_bits(index_bits(size))? as u32;
+        if index >= size {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("LZ78: фрази {index} ще немає у словнику"),
+            ));
+        }
+
+        word.clear();
+        let mut i = index;
+        while i != 0 {
+            let (parent, c) = phrases[i as usize];
+            word.push(c);
+            i = parent;
+        }
+        word.reverse();
+        if written + word.len() as u64 > total {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "LZ78: фраза виходить за кількість байтів із заголовка",
+            ));
+        }
+        writer.write_all(&word)?;
+        written += word.len() as u64;
+        // вхід закінчився посеред фрази: остання пара без байта
+        if written == total {
+            break;
+        }
+
+        let c = ds.read_bits(8)? as u8;
+        writer.write_all(&[c])?;
+        written += 1;
+        if size == full {
+            phrases.truncate(1);
+        } else {
+            phrases.push((index, c));
+        }
+    }
+
+    writer.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp(name: &str) -> String {
+        let path = std::env::temp_dir().join(format!("lz78-test-{}-{name}", std::process::id()));
+        path.to_str().unwrap().to_string()
+    }
+
+    // Кодує файл input, розкодовує назад і повертає розкодоване.
+    fn round_trip_file(name: &str, input: &str, max_bits: usize) -> Vec<u8> {
+        let packed = temp(&format!("{name}.l78"));
+        let output = temp(&format!("{name}.out"));
+        encode_with(input, &packed, max_bits).unwrap();
+        decode(&packed, &output).unwrap();
+        let result = std::fs::read(&output).unwrap();
+        std::fs::remove_file(packed).unwrap();
+        std::fs::remove_file(output).unwrap();
+        result
+    }
+
+    fn round_trip(name: &str, data: &[u8], max_bits: usize) {
+        let input = temp(name);
+        std::fs::write(&input, data).unwrap();
+        let result = round_trip_file(name, &input, max_bits);
+        std::fs::remove_file(input).unwrap();
+        assert!(result == data, "{name}: {} байт, max_bits {max_bits}", data.len());
+    }
+
+    #[test]
+    fn index_widths() {
+        let widths: Vec<usize> = (1..=9).map(index_bits).collect();
+        assert_eq!(widths, [0, 1, 2, 2, 3, 3, 3, 3, 4]);
+        assert_eq!(index_bits(1 << MAX_BITS), MAX_BITS);
+    }
+
+    #[test]
+    fn width_growth_boundaries() {
+        // два символи дають довгі фрази, тож кожен префікс закінчується то
+        // посеред фрази, то на межі; за 700 байтів словник на 4 і 6 бітів
+        // переповнюється кілька разів, а на 10 - проходить усі ширини до 8
+        let mut x = 7u32;
+        let data: Vec<u8> = (0..700)
+            .map(|_| {
+                x = x.wrapping_mul(1103515245).wrapping_add(12345);
+                b'a' + (x >> 30 & 1) as u8
+            })
+            .collect();
+        for max_bits in [1, 4, 6, 10] {
+            for len in 0..=data.len() {
+                round_trip("growth", &data[..len], max_bits);
+            }
+        }
+    }
+
+    // Розмір у metadata не завжди дорівнює вмісту: у /proc він нульовий.
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn size_from_content() {
+        let data = std::fs::read("/proc/version").unwrap();
+        assert!(!data.is_empty());
+        assert_eq!(round_trip_file("proc", "/proc/version", DEFAULT_MAX_BITS), data);
+    }
+}
@@ -0,0 +1,561 @@
+use crate::huffman;
+use bs::{BitReader, BitWriter};
+
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Error, ErrorKind, Read, Result, Write};
+
+// Файл: "LZS", версія, параметри вікна і збігів, далі блоки: довжина вихідних
+// даних блоку u32 і три секції з довжиною u32 попереду - літерали/довжини і
+// слоти відстаней (кожна - послідовність символів huffman::encode_symbols) та
+// додаткові біти відстаней. Блок нульової довжини завершує файл. Збіги можуть
+// сягати в попередні блоки на відстань до розміру вікна.
+const MAGIC: &[u8; 3] = b"LZS";
+const VERSION: u8 = 1;
+const HEADER_LEN: usize = 3 + 1 + 1 + 2 + 2;
+
+// найменший блок; з великим вікном блок дорівнює вікну, щоб повторна вставка
+// історії в ланцюжки коштувала не більше за сам блок
+const BLOCK_SIZE: usize = 1 << 20;
+
+const HASH_BITS: u32 = 16;
+const NONE: u32 = u32::MAX;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Token {
+    Literal(u8),
+    // відстань 1 - попередній байт
+    Match { dist: u32, len: u32 },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Params {
+    // вікно 2^window_bits байт
+    pub window_bits: usize,
+    pub min_match: usize,
+    pub max_match: usize,
+    // скільки разів можна відкласти збіг заради довшого з наступної позиції
+    // (0 - жадібний пошук)
+    pub lazy: usize,
+    // найбільше кандидатів, що переглядаються в ланцюжку хешу
+    pub chain: usize,
+}
+
+impl Default for Params {
+    fn default() -> Self {
+        Self {
+            window_bits: 15,
+            min_match: 3,
+            max_match: 258,
+            lazy: 1,
+            chain: 128,
+        }
+    }
+}
+
+impl Params {
+    pub const MIN_WINDOW_BITS: usize = 8;
+    pub const MAX_WINDOW_BITS: usize = 24;
+    pub const MAX_MATCH: usize = 1 << 16;
+
+    fn check(&self) -> Result<()> {
+        if !(Self::MIN_WINDOW_BITS..=Self::MAX_WINDOW_BITS).contains(&self.window_bits) {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!(
+                    "LZSS: вікно має бути від 2^{} до 2^{} байт",
+                    Self::MIN_WINDOW_BITS,
+                    Self::MAX_WINDOW_BITS
+                ),
+            ));
+        }
+        if self.min_match < 3 || self.min_match > self.max_match || self.max_match > Self::MAX_MATCH {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!(
+                    "LZSS: довжини збігу мають бути 3 <= min <= max <= {}",
+                    Self::MAX_MATCH
+                ),
+            ));
+        }
+        Ok(())
+    }
+
+    // Найбільша відстань: позиція dist тому має ще бути у вікні.
+    fn max_dist(&self) -> usize {
+        (1 << self.window_bits) - 1
+    }
+
+    fn block_size(&self) -> usize {
+        BLOCK_SIZE.max(1 << self.window_bits)
+    }
+}
+
+// Ланцюжки хешів: head - остання позиція з даним хешем перших трьох байтів,
+// prev - попередня позиція з тим самим хешем (за модулем вікна).
+struct Chains<'a> {
+    data: &'a [u8],
+    params: Params,
+    head: Vec<u32>,
+    prev: Vec<u32>,
+}
+
+impl<'a> Chains<'a> {
+    fn new(data: &'a [u8], params: Params) -> Self {
+        Self {
+            data,
+            params,
+            head: vec![NONE; 1 << HASH_BITS],
+            prev: vec![NONE; 1 << params.window_bits],
+        }
+    }
+
+    fn hash(&self, pos: usize) -> usize {
+        let d = self.data;
+        let x = (d[pos] as u32) << 16 | (d[pos + 1] as u32) << 8 | d[pos + 2] as u32;
+        (x.wrapping_mul(2654435761) >> (32 - HASH_BITS)) as usize
+    }
+
+    fn insert(&mut self, pos: usize) {
+        if pos + 3 > self.data.len() {
+            return;
+        }
+        let h = self.hash(pos);
+        let mask = self.prev.len() - 1;
+        self.prev[pos & mask] = self.head[h];
+        self.head[h] = pos as u32;
+    }
+
+    // Найдовший збіг для pos серед уже вставлених позицій: (відстань, довжина).
+    fn find(&self, pos: usize) -> (usize, usize) {
+        let max_len = self.params.max_match.min(self.data.len() - pos);
+        if max_len < self.params.min_match {
+            return (0, 0);
+        }
+        let mask = self.prev.len() - 1;
+        let (mut best_dist, mut best_len) = (0, 0);
+        let mut cand = self.head[self.hash(pos)];
+        let mut steps = 0;
+        while cand != NONE && steps < self.params.chain {
+            let cand_pos = cand as usize;
+            let dist = pos - cand_pos;
+            if dist > self.params.max_dist() {
+                break;
+            }
+            // спершу байт за кінцем найкращого збігу: інакше довшим не буде
+            if self.data[cand_pos + best_len] == self.data[pos + best_len] {
+                let len = self.data[cand_pos..cand_pos + max_len]
+                    .iter()
+                    .zip(&self.data[pos..pos + max_len])
+                    .take_while(|(a, b)| a == b)
+                    .count();
+                if len > best_len {
+                    (best_dist, best_len) = (dist, len);
+                    if len == max_len {
+                        break;
+                    }
+                }
+            }
+            cand = self.prev[cand_pos & mask];
+            steps += 1;
+        }
+        if best_len < self.params.min_match {
+            return (0, 0);
+        }
+        (best_dist, best_len)
+    }
+}
+
+// Токени для data[start..]; байти до start - історія, на яку можуть
+// посилатися збіги.
+pub fn tokenize(data: &[u8], start: usize, params: Params) -> Result<Vec<Token>> {
+    params.check()?;
+    let mut chains = Chains::new(data, params);
+    for pos in start.saturating_sub(params.max_dist())..start {
+        chains.insert(pos);
+    }
+    let mut tokens = Vec::new();
+    let mut pos = start;
+    // інваріант: усі позиції до pos уже вставлені в ланцюжки
+    while pos < data.len() {
+        let (mut dist, mut len) = chains.find(pos);
+        if len == 0 {
+            tokens.push(Token::Literal(data[pos]));
+            chains.insert(pos);
+            pos += 1;
+            continue;
+        }
+
+        // ледачий пошук: якщо з наступної позиції збіг довший, поточний
+        // байт іде літералом
+        for _ in 0..params.lazy {
+            if len == params.max_match || pos + 1 >= data.len() {
+                break;
+            }
+            chains.insert(pos);
+            let (next_dist, next_len) = chains.find(pos + 1);
+            if next_len <= len {
+                // позиція pos вже вставлена, наступна вставка почнеться з pos + 1
+                for p in pos + 1..pos + len {
+                    chains.insert(p);
+                }
+                tokens.push(Token::Match {
+                    dist: dist as u32,
+                    len: len as u32,
+                });
+                pos += len;
+                len = 0;
+                break;
+            }
+            tokens.push(Token::Literal(data[pos]));
+            pos += 1;
+            (dist, len) = (next_dist, next_len);
+        }
+        if len == 0 {
+            continue;
+        }
+
+        for p in pos..pos + len {
+            chains.insert(p);
+        }
+        tokens.push(Token::Match {
+            dist: dist as u32,
+            len: len as u32,
+        });
+        pos += len;
+    }
+    Ok(tokens)
+}
+
+// Дописує розкодований токен до out; збіги посилаються на вже записане.
+pub fn push_token(out: &mut Vec<u8>, token: Token) -> Result<()> {
+    match token {
+        Token::Literal(c) => out.push(c),
+        Token::Match { dist, len } => {
+            let (dist, len) = (dist as usize, len as usize);
+            if dist == 0 || dist > out.len() {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    format!("LZSS: відстань {dist} за межами вже розкодованих {} байт", out.len()),
+                ));
+            }
+            // збіг може перекривати сам себе, тому побайтово
+            let start = out.len() - dist;
+            for i in 0..len {
+                out.push(out[start + i]);
+            }
+        }
+    }
+    Ok(())
+}
+
+// Слот відстані - кількість значущих бітів dist; решта бітів, крім старшого,
+// пишуться як є.
+fn dist_slot(dist: u32) -> (u32, u32, usize) {
+    let slot = 32 - dist.leading_zeros();
+    let extra_bits = slot as usize - 1;
+    (slot, dist - (1 << extra_bits), extra_bits)
+}
+
+fn write_section(writer: &mut impl Write, section: &[u8]) -> Result<()> {
+    writer.write_all(&(section.len() as u32).to_le_bytes())?;
+    writer.write_all(section)
+}
+
+fn truncated() -> Error {
+    Error::new(ErrorKind::UnexpectedEof, "LZSS: файл обірвано")
+}
+
+fn read_u32(reader: &mut impl Read) -> Result<u32> {
+    let mut buf = [0u8; 4];
+    reader.read_exact(&mut buf).map_err(|e| match e.kind() {
+        ErrorKind::UnexpectedEof => truncated(),
+        _ => e,
+    })?;
+    Ok(u32::from_le_bytes(buf))
+}
+
+fn read_section(reader: &mut impl Read) -> Result<Vec<u8>> {
+    let len = read_u32(reader)? as u64;
+    // без попереднього виділення: довжину з пошкодженого файлу не перевірити
+    let mut section = Vec::new();
+    reader.take(len).read_to_end(&mut section)?;
+    if section.len() as u64 != len {
+        return Err(truncated());
+    }
+    Ok(section)
+}
+
+fn write_block(writer: &mut impl Write, tokens: &[Token], size: usize, params: Params) -> Result<()> {
+    // літерали 0..=255, довжини збігів - від 256
+    let mut litlen: Vec<u32> = Vec::with_capacity(tokens.len());
+    let mut slots: Vec<u32> = Vec::new();
+    let mut extra = BitWriter::new(Vec::new());
+    for &token in tokens {
+        match token {
+            Token::Literal(c) => litlen.push(c as u32),
+            Token::Match { dist, len } => {
+                litlen.push(256 + len - params.min_match as u32);
+                let (slot, bits, n) = dist_slot(dist);
+                slots.push(slot);
+                extra.write_bits(bits as u64, n)?;
+            }
+        }
+    }
+    let litlen_alphabet = 256 + params.max_match - params.min_match + 1;
+    let slot_alphabet = params.window_bits + 1;
+
+    writer.write_all(&(size as u32).to_le_bytes())?;
+    write_section(writer, &huffman::encode_symbols(&litlen, litlen_alphabet)?)?;
+    write_section(writer, &huffman::encode_symbols(&slots, slot_alphabet)?)?;
+    write_section(writer, &extra.finish()?)
+}
+
+pub fn encode(file_read: &str, file_write: &str, params: Params) -> Result<()> {
+    params.check()?;
+    let mut reader = BufReader::new(File::open(file_read)?);
+    let mut writer = BufWriter::new(File::create(file_write)?);
+    writer.write_all(MAGIC)?;
+    writer.write_all(&[VERSION, params.window_bits as u8])?;
+    writer.write_all(&(params.min_match as u16).to_le_bytes())?;
+    // max_match до 2^16 включно, тому зберігається max_match - 1
+    writer.write_all(&((params.max_match - 1) as u16).to_le_bytes())?;
+
+    // хвіст попередніх блоків у межах вікна, за ним поточний блок
+    let block = params.block_size();
+    let mut data = Vec::with_capacity(params.max_dist() + block);
+    loop {
+        let history = data.len();
+        let n = (&mut reader).take(block as u64).read_to_end(&mut data)?;
+        if n == 0 {
+            break;
+        }
+        let tokens = tokenize(&data, history, params)?;
+        write_block(&mut writer, &tokens, n, params)?;
+        data.drain(..data.len().saturating_sub(params.max_dist()));
+    }
+    writer.write_all(&0u32.to_le_bytes())?;
+    writer.flush()
+}
+
+pub fn decode(file_read: &str, file_write: &str) -> Result<()> {
+    let mut reader = BufReader::new(File::open(file_read)?);
+    let mut header = [0u8; HEADER_LEN];
+    let unknown = || Error::new(ErrorKind::InvalidData, "LZSS: невідомий формат або версія");
+    match reader.read_exact(&mut header) {
+        Err(e) if e.kind() == ErrorKind::UnexpectedEof => return Err(unknown()),
+        result => result?,
+    }
+    if &header[..3] != MAGIC || header[3] != VERSION {
+        return Err(unknown());
+    }
+    let window_bits = header[4] as usize;
+    let min_match = u16::from_le_bytes([header[5], header[6]]) as usize;
+    let max_match = u16::from_le_bytes([header[7], header[8]]) as usize + 1;
+    let params = Params {
+        window_bits,
+        min_match,
+        max_match,
+        ..Params::default()
+    };
+    params
+        .check()
+        .map_err(|e| Error::new(ErrorKind::InvalidData, e.to_string()))?;
+
+    let corrupt = |msg: &str| Error::new(ErrorKind::InvalidData, format!("LZSS: {msg}"));
+    let mut writer = BufWriter::new(File::create(file_write)?);
+    // як у кодері: хвіст у межах вікна, за ним поточний блок
+    let mut out = Vec::new();
+    loop {
+        let size = read_u32(&mut reader)? as usize;
+        if size == 0 {
+            break;
+        }
+        if size > params.block_size() {
+            return Err(corrupt("блок більший за допустимий"));
+        }
+        let litlen: Vec<u32> = huffman::decode_symbols(&read_section(&mut reader)?)?;
+        let slots: Vec<u32> = huffman::decode_symbols(&read_section(&mut reader)?)?;
+        let extra = read_section(&mut reader)?;
+        let mut extra = BitReader::new(&extra[..]);
+
+        let history = out.len();
+        let mut slots = slots.into_iter();
+        for x in litlen {
+            if out.len() - history >= size {
+                return Err(corrupt("довжина розкодованого блоку не збігається із заголовком"));
+            }
+            if x < 256 {
+                out.push(x as u8);
+                continue;
+            }
+            // символ має бути меншим за 256 + (max_match - min_match + 1), інакше
+            // довжина поза межами, а з великим x ще й переповнює u32
+            if x as usize - 256 > max_match - min_match {
+                return Err(corrupt("довжина збігу поза межами"));
+            }
+            let len = x - 256 + min_match as u32;
+            let slot = slots.next().ok_or_else(|| corrupt("бракує відстаней"))?;
+            if slot == 0 || slot as usize > window_bits {
+                return Err(corrupt("недопустимий слот відстані"));
+            }
+            let n = slot as usize - 1;
+            let dist = (1 << n) + extra.read_bits(n)? as u32;
+            push_token(&mut out, Token::Match { dist, len })?;
+        }
+        if out.len() - history != size {
+            return Err(corrupt("довжина розкодованого блоку не збігається із заголовком"));
+        }
+        writer.write_all(&out[history..])?;
+        out.drain(..out.len().saturating_sub(params.max_dist()));
+    }
+    writer.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp(name: &str) -> String {
+        let path = std::env::temp_dir().join(format!("lzss-test-{}-{name}", std::process::id()));
+        path.to_str().unwrap().to_string()
+    }
+
+    // Кодує data у файл, розкодовує назад і повертає стиснуті байти.
+    fn round_trip(name: &str, data: &[u8], params: Params) -> Vec<u8> {
+        let input = temp(name);
+        let packed = temp(&format!("{name}.lzs"));
+        let output = temp(&format!("{name}.out"));
+        std::fs::write(&input, data).unwrap();
+        encode(&input, &packed, params).unwrap();
+        decode(&packed, &output).unwrap();
+        let result = (std::fs::read(&packed).unwrap(), std::fs::read(&output).unwrap());
+        for path in [input, packed, output] {
+            std::fs::remove_file(path).unwrap();
+        }
+        assert!(result.1 == data, "{name}: розкодоване не збігається з вхідним");
+        result.0
+    }
+
+    fn decode_bytes(name: &str, packed: &[u8]) -> Result<Vec<u8>> {
+        let input = temp(name);
+        let output = temp(&format!("{name}.out"));
+        std::fs::write(&input, packed).unwrap();
+        let result = decode(&input, &output).and_then(|_| std::fs::read(&output));
+        let _ = std::fs::remove_file(input);
+        let _ = std::fs::remove_file(output);
+        result
+    }
+
+    fn noise(len: usize, mut x: u64) -> Vec<u8> {
+        (0..len)
+            .map(|_| {
+                x ^= x << 13;
+                x ^= x >> 7;
+                x ^= x << 17;
+                x as u8
+            })
+            .collect()
+    }
+
+    fn matches(tokens: &[Token]) -> impl Iterator<Item = (u32, u32)> + '_ {
+        tokens.iter().filter_map(|&t| match t {
+            Token::Match { dist, len } => Some((dist, len)),
+            Token::Literal(_) => None,
+        })
+    }
+
+    #[test]
+    fn window_edge_distances() {
+        let params = Params {
+            window_bits: 8,
+            ..Params::default()
+        };
+        let repeat = noise(64, 1);
+        // повтор рівно на найбільшій відстані знаходиться, на одну далі - ні
+        for (gap, found) in [(255 - 64, true), (256 - 64, false)] {
+            let mut data = repeat.clone();
+            data.extend(noise(gap, 2));
+            data.extend(&repeat);
+            let tokens = tokenize(&data, 0, params).unwrap();
+            assert!(matches(&tokens).all(|(dist, _)| dist as usize <= params.max_dist()));
+            assert_eq!(matches(&tokens).any(|m| m == (255, 64)), found, "gap {gap}");
+            round_trip(&format!("edge-{gap}"), &data, params);
+        }
+    }
+
+    #[test]
+    fn match_lengths() {
+        // найкоротший збіг - min_match, коротший іде літералами
+        for (min_match, found) in [(3, true), (4, false)] {
+            let params = Params {
+                min_match,
+                ..Params::default()
+            };
+            let tokens = tokenize(b"abcXabc", 0, params).unwrap();
+            assert_eq!(matches(&tokens).any(|m| m == (4, 3)), found, "min {min_match}");
+            round_trip(&format!("min-{min_match}"), b"abcXabc", params);
+        }
+
+        // довгі серії ріжуться на збіги не довші за max_match, аж до MAX_MATCH
+        let data = vec![b'a'; 200_000];
+        for max_match in [3, 258, Params::MAX_MATCH] {
+            let params = Params {
+                max_match,
+                ..Params::default()
+            };
+            let tokens = tokenize(&data, 0, params).unwrap();
+            assert!(matches(&tokens).all(|(_, len)| len as usize <= max_match));
+            assert!(matches(&tokens).any(|(_, len)| len as usize == max_match));
+            round_trip(&format!("max-{max_match}"), &data, params);
+        }
+    }
+
+    #[test]
+    fn overlapping_copies() {
+        let mut out = b"ab".to_vec();
+        push_token(&mut out, Token::Match { dist: 2, len: 7 }).unwrap();
+        assert_eq!(out, b"ababababa");
+        push_token(&mut out, Token::Match { dist: 1, len: 3 }).unwrap();
+        assert_eq!(out, b"ababababaaaa");
+
+        // кодер сам дає dist < len на періодичних даних
+        let data = b"xyz".repeat(1000);
+        let tokens = tokenize(&data, 0, Params::default()).unwrap();
+        assert!(matches(&tokens).any(|(dist, len)| dist < len));
+        round_trip("overlap", &data, Params::default());
+    }
+
+    #[test]
+    fn blocks() {
+        let packed = round_trip("empty", b"", Params::default());
+        assert_eq!(packed.len(), HEADER_LEN + 4);
+
+        // другий блок - копія кінця першого: збіг через межу блоків
+        let mut data = noise(BLOCK_SIZE, 3);
+        data.extend_from_within(BLOCK_SIZE - 4096..);
+        let packed = round_trip("blocks", &data, Params::default());
+        assert!(packed.len() < BLOCK_SIZE + 1024, "{} байт", packed.len());
+    }
+
+    #[test]
+    fn corrupt_streams() {
+        let params = Params::default();
+        let mut data = b"abcabcabd".repeat(50);
+        data.extend(noise(100, 4));
+        let packed = round_trip("corrupt", &data, params);
+        for len in 0..packed.len() {
+            assert!(decode_bytes("corrupt-cut", &packed[..len]).is_err(), "обрізано до {len}");
+        }
+
+        // символ довжини одразу за алфавітом
+        let alphabet = 256 + params.max_match - params.min_match + 1;
+        let mut bad = packed[..HEADER_LEN].to_vec();
+        bad.extend(10u32.to_le_bytes());
+        write_section(&mut bad, &huffman::encode_symbols(&[97u32, alphabet as u32], alphabet + 1).unwrap()).unwrap();
+        write_section(&mut bad, &huffman::encode_symbols(&[1u32], params.window_bits + 1).unwrap()).unwrap();
+        write_section(&mut bad, &[]).unwrap();
+        bad.extend(0u32.to_le_bytes());
+        let err = decode_bytes("corrupt-len", &bad).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+    }
+}
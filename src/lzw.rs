@@ -1,444 +1,2131 @@
-#![allow(dead_code, unused, non_snake_case)]
+#![allow(non_snake_case)]
 
-use bs::{BitStream, Mode};
+use bs::{BitReader, BitStream, BitWriter, Mode};
 
 use std::collections::HashMap;
 use std::fs::File;
-use std::io::{BufReader, BufWriter, Read, Result, Write};
+use std::io::{BufReader, BufWriter, Error, ErrorKind, Read, Result, Write};
+use std::time::{Duration, Instant};
 
-const CLEAR_CODE: usize = 256;
-const END_CODE: usize = 257;
+const CLEAR_CODE: u32 = 256;
+const END_CODE: u32 = 257;
+// Перший код, що відводиться під нові слова словника.
+const FIRST_CODE: u32 = 258;
+
+// Заголовок: "LZW", версія, мінімальна і максимальна ширина коду, політика
+// заповненого словника, спосіб поповнення словника (з версії 3), u32 ID
+// словника, з якого починають кодер і декодер (з версії 4, 0 - без словника).
+const MAGIC: &[u8; 3] = b"LZW";
+const VERSION: u8 = 4;
+const MIN_BITS: usize = 9;
+const MAX_BITS: usize = 24;
+// Для Policy::Ratio: як часто (в байтах входу) перевіряти коефіцієнт стиснення.
+const CHECK_GAP: u64 = 10000;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Policy {
+    // Очистити словник (CLEAR_CODE) щойно він заповнився.
+    Reset,
+    // Більше не додавати слів, кодувати заповненим словником.
+    Freeze,
+    // Як compress -b: заморозити, а очистити коли коефіцієнт стиснення падає.
+    Ratio,
+}
+
+// Які слова потрапляють у словник після чергового слова.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Growth {
+    // попереднє слово + перший байт поточного
+    Lzw,
+    // попереднє слово + поточне слово
+    Lzmw,
+    // попереднє слово + кожен префікс поточного
+    Lzap,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Options {
+    pub min_bits: usize,
+    pub max_bits: usize,
+    pub policy: Policy,
+    pub growth: Growth,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            min_bits: 9,
+            max_bits: 21,
+            policy: Policy::Reset,
+            growth: Growth::Lzw,
+        }
+    }
+}
+
+impl Options {
+    fn check(&self) -> Result<()> {
+        if !(MIN_BITS..=self.max_bits).contains(&self.min_bits) || self.max_bits > MAX_BITS {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("LZW: ширина коду має бути в межах {MIN_BITS}..={MAX_BITS} біт"),
+            ));
+        }
+        Ok(())
+    }
+
+    fn write_header(&self, writer: &mut impl Write, dict_id: u32) -> Result<()> {
+        let policy = match self.policy {
+            Policy::Reset => 0,
+            Policy::Freeze => 1,
+            Policy::Ratio => 2,
+        };
+        let growth = match self.growth {
+            Growth::Lzw => 0,
+            Growth::Lzmw => 1,
+            Growth::Lzap => 2,
+        };
+        writer.write_all(MAGIC)?;
+        writer.write_all(&[VERSION, self.min_bits as u8, self.max_bits as u8, policy, growth])?;
+        writer.write_all(&dict_id.to_le_bytes())
+    }
+
+    // Параметри потоку та ID словника (0, якщо потік без словника).
+    fn read_header(reader: &mut impl Read) -> Result<(Self, u32)> {
+        let mut header = [0u8; 7];
+        reader.read_exact(&mut header).map_err(|e| match e.kind() {
+            ErrorKind::UnexpectedEof => DecodeError::Truncated.into(),
+            _ => e,
+        })?;
+        if &header[..3] != MAGIC || !(2..=VERSION).contains(&header[3]) {
+            return Err(DecodeError::Header("невідомий формат або версія").into());
+        }
+        // у версії 2 ще не було байта Growth
+        let mut growth = [0u8];
+        if header[3] >= 3 {
+            reader.read_exact(&mut growth).map_err(|e| match e.kind() {
+                ErrorKind::UnexpectedEof => DecodeError::Truncated.into(),
+                _ => e,
+            })?;
+        }
+        let mut dict_id = [0u8; 4];
+        if header[3] >= 4 {
+            reader.read_exact(&mut dict_id).map_err(|e| match e.kind() {
+                ErrorKind::UnexpectedEof => DecodeError::Truncated.into(),
+                _ => e,
+            })?;
+        }
+        let growth = match growth[0] {
+            0 => Growth::Lzw,
+            1 => Growth::Lzmw,
+            2 => Growth::Lzap,
+            _ => return Err(DecodeError::Header("невідомий спосіб поповнення словника").into()),
+        };
+        let policy = match header[6] {
+            0 => Policy::Reset,
+            1 => Policy::Freeze,
+            2 => Policy::Ratio,
+            _ => return Err(DecodeError::Header("невідома політика словника").into()),
+        };
+        let opts = Self {
+            min_bits: header[4] as usize,
+            max_bits: header[5] as usize,
+            policy,
+            growth,
+        };
+        if opts.check().is_err() {
+            return Err(DecodeError::Header("недопустима ширина коду").into());
+        }
+        Ok((opts, u32::from_le_bytes(dict_id)))
+    }
+}
+
+// Стан, спільний для кодера і декодера: наступний вільний код і поточна
+// ширина коду. У LZW кожен виданий (прочитаний) код даних резервує наступний
+// код словника, у LZMW/LZAP код бере кожне нове слово; так чи інакше обидві
+// сторони змінюють ширину в одній і тій самій точці.
+struct Codes {
+    opts: Options,
+    // перший вільний код після очищення (FIRST_CODE + слова словника)
+    first: u32,
+    size: u32,
+    bits: usize,
+}
+
+impl Codes {
+    fn new(opts: Options) -> Self {
+        Self::primed(opts, 0)
+    }
+
+    // Коди, що починаються після words слів заздалегідь навченого словника.
+    fn primed(opts: Options, words: usize) -> Self {
+        let mut codes = Self {
+            opts,
+            first: FIRST_CODE + words as u32,
+            size: 0,
+            bits: 0,
+        };
+        codes.reset();
+        codes
+    }
+
+    fn reset(&mut self) {
+        self.size = self.first;
+        self.bits = self.opts.min_bits;
+        while self.size >= 1 << self.bits && self.bits < self.opts.max_bits {
+            self.bits += 1;
+        }
+    }
+
+    fn full(&self) -> bool {
+        self.size == 1 << self.opts.max_bits
+    }
+
+    // Після коду даних: повертає код нового слова, якщо словник ще росте.
+    fn next(&mut self) -> Option<u32> {
+        if self.full() {
+            return None;
+        }
+        let code = self.size;
+        self.size += 1;
+        if self.size == 1 << self.bits && self.bits < self.opts.max_bits {
+            self.bits += 1;
+        }
+        Some(code)
+    }
+}
 
 pub fn encode(file_read: &str, file_write: &str) -> Result<()> {
-    let mut reader = BufReader::new(File::open(file_read)?);
+    encode_with(file_read, file_write, Options::default())
+}
+
+// Коли кодер має очистити словник (див. Policy).
+struct Clearing {
+    policy: Policy,
+    in_bytes: u64,
+    out_bits: u64,
+    checkpoint: u64,
+    best_ratio: f64,
+}
 
-    let mut dict: HashMap<(u16, Option<u32>), u32> = HashMap::new();
-    let mut ds = BitStream::new(file_write, Mode::Write);
+impl Clearing {
+    fn new(policy: Policy) -> Self {
+        Self {
+            policy,
+            in_bytes: 0,
+            out_bits: 0,
+            checkpoint: CHECK_GAP,
+            best_ratio: 0.0,
+        }
+    }
 
-    for i in 0..=255 {
-        dict.insert((i, None), i as u32);
+    fn check(&mut self, codes: &Codes) -> bool {
+        let clear = match self.policy {
+            Policy::Reset => codes.full(),
+            Policy::Freeze => false,
+            Policy::Ratio if codes.full() && self.in_bytes >= self.checkpoint => {
+                self.checkpoint = self.in_bytes + CHECK_GAP;
+                let ratio = self.in_bytes as f64 / self.out_bits as f64;
+                if ratio > self.best_ratio {
+                    self.best_ratio = ratio;
+                    false
+                } else {
+                    true
+                }
+            }
+            Policy::Ratio => false,
+        };
+        if clear {
+            *self = Self::new(self.policy);
+        }
+        clear
     }
+}
 
-    dict.insert((256, Some(256)), CLEAR_CODE as u32);
-    dict.insert((257, Some(257)), END_CODE as u32);
+pub fn encode_with(file_read: &str, file_write: &str, opts: Options) -> Result<()> {
+    encode_file(file_read, file_write, opts, None)
+}
+
+// Кодування, що починається зі словника dict; декодувати тим самим словником
+// через decode_with_dict. Лише для Growth::Lzw.
+pub fn encode_with_dict(
+    file_read: &str,
+    file_write: &str,
+    opts: Options,
+    dict: &Dictionary,
+) -> Result<()> {
+    encode_file(file_read, file_write, opts, Some(dict))
+}
+
+fn encode_file(
+    file_read: &str,
+    file_write: &str,
+    opts: Options,
+    dict: Option<&Dictionary>,
+) -> Result<()> {
+    let reader = File::open(file_read)?;
+    let writer = File::create(file_write)?;
+    encode_stream_with(reader, writer, opts, dict)
+}
+
+// Кодування в пам'яті для тестів і fuzz-цілі lzw_decode; CLI працює з файлами.
+#[allow(dead_code)]
+pub fn encode_bytes(data: &[u8], opts: Options) -> Result<Vec<u8>> {
+    let mut out = Vec::new();
+    encode_stream_with(data, &mut out, opts, None)?;
+    Ok(out)
+}
 
-    let s: u8 = 0;
+pub fn encode_stream_with(
+    reader: impl Read,
+    mut writer: impl Write,
+    opts: Options,
+    dict: Option<&Dictionary>,
+) -> Result<()> {
+    opts.check()?;
+    if let Some(dict) = dict {
+        if opts.growth != Growth::Lzw {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "LZW: словник підтримується лише для Growth::Lzw",
+            ));
+        }
+        dict.check(opts)?;
+    }
+
+    opts.write_header(&mut writer, dict.map_or(0, |d| d.id))?;
+    let mut ds = BitWriter::new(writer);
+
+    let words = dict.map_or(&[][..], |d| &d.words);
+    match opts.growth {
+        Growth::Lzw => encode_lzw(reader, &mut ds, opts, words)?,
+        Growth::Lzmw | Growth::Lzap => encode_phrases(reader, &mut ds, opts)?,
+    }
+
+    ds.finish()?;
+    Ok(())
+}
+
+// Розмір буфера, яким кодер читає вхід.
+const INPUT_BUF: usize = 1 << 20;
+
+// Словник кодера LZW. Слова з однобайтовим префіксом (перший крок кожного
+// слова) лежать у прямій таблиці 256 x 256, решта - у відкритій адресації з
+// лінійним пробуванням, де ключ (префікс, байт) пакується в u32, бо коди не
+// ширші за MAX_BITS = 24. Домашня комірка береться не з ключа, а з хешу байтів
+// самого слова (Table::step), який кодер рахує по ходу: адреса наступного
+// пошуку тоді не залежить від коду, знайденого попереднім, і процесор вантажить
+// кілька комірок одночасно. Комірок удвічі більше за 2^max_bits, тож таблиця
+// не росте; код 0 (кодів слів нижче FIRST_CODE не буває) позначає вільну
+// комірку, і нульова пам'ять береться в ОС сторінками лише під час запису.
+struct Table {
+    roots: Vec<u32>,
+    // заповнені комірки roots, щоб не чистити всю таблицю після CLEAR_CODE
+    used: Vec<u16>,
+    // (ключ, код); EMPTY у коді - вільна комірка
+    slots: Vec<(u32, u32)>,
+    shift: u32,
+}
+
+impl Table {
+    const EMPTY: u32 = 0;
+
+    fn new(max_bits: usize) -> Self {
+        Self {
+            roots: vec![Self::EMPTY; 256 * 256],
+            used: Vec::new(),
+            slots: vec![(0, Self::EMPTY); 2 << max_bits],
+            shift: 64 - (max_bits as u32 + 1),
+        }
+    }
+
+    // Хеш однобайтового слова і слова, подовженого на байт c.
+    fn start(c: u8) -> u64 {
+        (c as u64 + 1) << 8
+    }
+
+    fn step(h: u64, c: u8) -> u64 {
+        (h ^ c as u64).wrapping_mul(0x9e37_79b9_7f4a_7c15)
+    }
+
+    fn clear(&mut self) {
+        for &i in &self.used {
+            self.roots[i as usize] = Self::EMPTY;
+        }
+        self.used.clear();
+        self.slots.fill((0, Self::EMPTY));
+    }
+
+    // Слова навченого словника з кодами від FIRST_CODE; префікс завжди
+    // з меншим кодом, тож хеш слова будується з хешу префікса.
+    fn fill(&mut self, words: &[(u32, u8)]) {
+        let mut hashes = Vec::with_capacity(words.len());
+        for (i, &(prefix, c)) in words.iter().enumerate() {
+            let h = match prefix.checked_sub(FIRST_CODE) {
+                Some(p) => Self::step(hashes[p as usize], c),
+                None => Self::step(Self::start(prefix as u8), c),
+            };
+            hashes.push(h);
+            if let Err(slot) = self.find(prefix, c, h) {
+                self.insert_at(slot, prefix, c, FIRST_CODE + i as u32);
+            }
+        }
+    }
+
+    // Код слова "prefix + c" з хешем h, а якщо його немає - комірка, куди
+    // його вставити (як binary_search), щоб не пробувати таблицю вдруге.
+    fn find(&self, prefix: u32, c: u8, h: u64) -> std::result::Result<u32, usize> {
+        let key = (prefix << 8) | c as u32;
+        if prefix < 256 {
+            return match self.roots[key as usize] {
+                Self::EMPTY => Err(key as usize),
+                code => Ok(code),
+            };
+        }
+        let mask = self.slots.len() - 1;
+        let mut i = (h >> self.shift) as usize;
+        loop {
+            let (k, code) = self.slots[i];
+            if code == Self::EMPTY {
+                return Err(i);
+            }
+            if k == key {
+                return Ok(code);
+            }
+            i = (i + 1) & mask;
+        }
+    }
+
+    // Вставка в комірку, яку щойно повернув find для того самого слова.
+    fn insert_at(&mut self, slot: usize, prefix: u32, c: u8, code: u32) {
+        if prefix < 256 {
+            self.roots[slot] = code;
+            self.used.push(slot as u16);
+        } else {
+            self.slots[slot] = ((prefix << 8) | c as u32, code);
+        }
+    }
+}
+
+fn encode_lzw(
+    mut reader: impl Read,
+    ds: &mut BitWriter<impl Write>,
+    opts: Options,
+    words: &[(u32, u8)],
+) -> Result<()> {
+    let mut table = Table::new(opts.max_bits);
+    table.fill(words);
+    let mut codes = Codes::primed(opts, words.len());
+    let mut clearing = Clearing::new(opts.policy);
+    let mut buf = vec![0u8; INPUT_BUF];
+    // поточне слово: код і хеш його байтів
+    let mut I: Option<(u32, u64)> = None;
+    // байтів прочитано до поточного буфера і до останнього очищення
+    let (mut consumed, mut cleared_at) = (0u64, 0u64);
+    loop {
+        let n = match reader.read(&mut buf) {
+            Ok(0) => break,
+            Ok(n) => n,
+            Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        let (mut prefix, mut h, skip) = match I {
+            Some((prefix, h)) => (prefix, h, 0),
+            None => (buf[0] as u32, Table::start(buf[0]), 1),
+        };
+        for (j, &c) in buf[..n].iter().enumerate().skip(skip) {
+            let next = Table::step(h, c);
+            let slot = match table.find(prefix, c, next) {
+                Ok(code) => {
+                    prefix = code;
+                    h = next;
+                    continue;
+                }
+                Err(slot) => slot,
+            };
+
+            ds.write_bits(prefix as u64, codes.bits)?;
+            clearing.out_bits += codes.bits as u64;
+            if let Some(code) = codes.next() {
+                table.insert_at(slot, prefix, c, code);
+            }
+
+            let pos = consumed + j as u64 + 1;
+            clearing.in_bytes = pos - cleared_at;
+            if clearing.check(&codes) {
+                ds.write_bits(CLEAR_CODE as u64, codes.bits)?;
+                table.clear();
+                table.fill(words);
+                codes.reset();
+                cleared_at = pos;
+            }
+
+            prefix = c as u32;
+            h = Table::start(c);
+        }
+        I = Some((prefix, h));
+        consumed += n as u64;
+    }
+    if let Some((prefix, _)) = I {
+        ds.write_bits(prefix as u64, codes.bits)?;
+        // декодер теж резервує код після кожного коду даних
+        codes.next();
+    }
+    ds.write_bits(END_CODE as u64, codes.bits)
+}
+
+// Попередній кодер на HashMap і побайтовому read_exact; база для bench_encoder.
+fn encode_hashmap(file_read: &str, file_write: &str, opts: Options) -> Result<()> {
+    opts.check()?;
+    let mut reader = BufReader::new(File::open(file_read)?);
+
+    let mut file = File::create(file_write)?;
+    opts.write_header(&mut file, 0)?;
+    let mut ds = BitStream::new_file(file, Mode::Write);
+
+    // (префікс, байт) -> код; однобайтові слова мають код, рівний байту
+    let mut dict: HashMap<(u32, u8), u32> = HashMap::new();
+    let mut codes = Codes::new(opts);
+    let mut clearing = Clearing::new(opts.policy);
     let mut c = [0];
     let mut I: Option<u32> = None;
-    let mut size = 258;
-    let mut write_bit = 9;
     while reader.read_exact(&mut c).is_ok() {
-        let c = c[0] as u16;
+        let c = c[0];
+        clearing.in_bytes += 1;
 
-        if let Some(&index) = dict.get(&(c, I)) {
+        let Some(prefix) = I else {
+            I = Some(c as u32);
+            continue;
+        };
+        if let Some(&index) = dict.get(&(prefix, c)) {
             I = Some(index);
-        } else {
-            // print!("({:?},{write_bit},{}) ", I.unwrap(), size);
+            continue;
+        }
+
+        ds.write_bit_sequence(&prefix.to_le_bytes(), codes.bits)?;
+        clearing.out_bits += codes.bits as u64;
+        if let Some(code) = codes.next() {
+            dict.insert((prefix, c), code);
+        }
+
+        if clearing.check(&codes) {
+            ds.write_bit_sequence(&CLEAR_CODE.to_le_bytes(), codes.bits)?;
+            dict.clear();
+            codes.reset();
+        }
+
+        I = Some(c as u32);
+    }
+    if let Some(prefix) = I {
+        ds.write_bit_sequence(&prefix.to_le_bytes(), codes.bits)?;
+        codes.next();
+    }
+    ds.write_bit_sequence(&END_CODE.to_le_bytes(), codes.bits)?;
+    ds.close()
+}
 
-            ds.write_bit_sequence(&I.unwrap().to_le_bytes(), write_bit);
+// Відтворюваний набір для bench_encoder: псевдотекст зі слів із
+// нерівномірними частотами і випадкові байти, обидва з фіксованим зерном.
+pub fn bench_corpus(size: usize) -> [(&'static str, Vec<u8>); 2] {
+    let mut x = 7u32;
+    let mut next = move || {
+        x = x.wrapping_mul(1103515245).wrapping_add(12345);
+        (x >> 16) as usize
+    };
+    let words: Vec<Vec<u8>> = (0..5000)
+        .map(|i| (0..2 + i * 7919 % 9).map(|j| b'a' + ((i * 31 + j * 17) % 26) as u8).collect())
+        .collect();
+    let mut text = Vec::with_capacity(size + 16);
+    while text.len() < size {
+        let (a, b) = (next(), next());
+        text.extend_from_slice(&words[a * a / 65536 * b / 65536 % words.len()]);
+        text.push(if next() % 12 == 0 { b'\n' } else { b' ' });
+    }
+    text.truncate(size);
+    let noise = (0..size).map(|_| next() as u8).collect();
+    [("text", text), ("noise", noise)]
+}
 
-            dict.insert((c, I), size);
-            size += 1;
+// Кодер проти encode_hashmap на file для кількох max_bits: найкращий час із
+// rounds запусків кожного; виходи мають збігатися байт у байт. Рядки звіту
+// підписуються name.
+pub fn bench_encoder(
+    name: &str,
+    file: &str,
+    packed: &str,
+    rounds: usize,
+    out: &mut impl Write,
+) -> Result<()> {
+    for max_bits in [12, 16, Options::default().max_bits] {
+        let opts = Options {
+            max_bits,
+            ..Options::default()
+        };
+        let (mut old, mut new) = (Duration::MAX, Duration::MAX);
+        for _ in 0..rounds {
+            let start = Instant::now();
+            encode_hashmap(file, packed, opts)?;
+            old = old.min(start.elapsed());
+            let expected = std::fs::read(packed)?;
 
-            if size == (1 << write_bit) {
-                write_bit += 1;
+            let start = Instant::now();
+            encode_with(file, packed, opts)?;
+            new = new.min(start.elapsed());
+            if std::fs::read(packed)? != expected {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    format!("LZW: кодер і encode_hashmap дали різний вихід на {name}"),
+                ));
             }
+        }
+        writeln!(
+            out,
+            "{name}\t{max_bits}\t{old:.3?}\t{new:.3?}\t{:.2}",
+            old.as_secs_f64() / new.as_secs_f64()
+        )?;
+    }
+    Ok(())
+}
 
-            if write_bit == 22 {
-                ds.write_bit_sequence(&CLEAR_CODE.to_le_bytes(), write_bit);
-                dict.clear();
-                for i in 0..=255 {
-                    dict.insert((i, None), i as u32);
+// Словник LZMW/LZAP як префіксне дерево, однакове в кодері й декодері.
+// Слово prev + cur з LZMW може не мати кодів у своїх префіксах, тому частина
+// вузлів проміжні, без коду.
+struct Trie {
+    // (батьківський вузол, байт); корені 0..=255 - однобайтові слова
+    nodes: Vec<(u32, u8)>,
+    children: HashMap<(u32, u8), u32>,
+    // код вузла, якщо є
+    code: Vec<Option<u32>>,
+    // вузол коду; CLEAR_CODE і END_CODE не мають вузлів
+    node: Vec<u32>,
+}
+
+impl Trie {
+    const NONE: u32 = u32::MAX;
+
+    fn new() -> Self {
+        let mut trie = Self {
+            nodes: Vec::new(),
+            children: HashMap::new(),
+            code: Vec::new(),
+            node: Vec::new(),
+        };
+        trie.reset();
+        trie
+    }
+
+    fn reset(&mut self) {
+        self.nodes.clear();
+        self.children.clear();
+        self.code.clear();
+        self.node.clear();
+        for c in 0..=255u32 {
+            self.nodes.push((Self::NONE, c as u8));
+            self.code.push(Some(c));
+            self.node.push(c);
+        }
+        self.node.extend([Self::NONE; (FIRST_CODE - CLEAR_CODE) as usize]);
+    }
+
+    // Вузол коду, якщо такий код уже є у словнику.
+    fn node_of(&self, code: u32) -> Option<u32> {
+        self.node.get(code as usize).copied().filter(|&n| n != Self::NONE)
+    }
+
+    fn word(&self, mut node: u32, word: &mut Vec<u8>) {
+        word.clear();
+        while node != Self::NONE {
+            let (parent, c) = self.nodes[node as usize];
+            word.push(c);
+            node = parent;
+        }
+        word.reverse();
+    }
+
+    // Найдовше слово з кодом на початку data: (вузол, довжина).
+    fn longest(&self, data: &[u8]) -> (u32, usize) {
+        let (mut node, mut best) = (data[0] as u32, (data[0] as u32, 1));
+        for (i, &c) in data.iter().enumerate().skip(1) {
+            match self.children.get(&(node, c)) {
+                Some(&child) => node = child,
+                None => break,
+            }
+            if self.code[node as usize].is_some() {
+                best = (node, i + 1);
+            }
+        }
+        best
+    }
+
+    // Додає слова, що утворюються з попереднього слова (вузол prev) і
+    // поточного cur; кожне нове слово забирає наступний код у codes.
+    fn grow(&mut self, growth: Growth, prev: u32, cur: &[u8], codes: &mut Codes) {
+        // у заповненому словнику нові вузли вже не отримали б кодів
+        if codes.full() {
+            return;
+        }
+        let mut node = prev;
+        for (i, &c) in cur.iter().enumerate() {
+            node = match self.children.get(&(node, c)) {
+                Some(&child) => child,
+                None => {
+                    let child = self.nodes.len() as u32;
+                    self.nodes.push((node, c));
+                    self.code.push(None);
+                    self.children.insert((node, c), child);
+                    child
                 }
-                dict.insert((256, None), CLEAR_CODE as u32);
-                dict.insert((257, None), END_CODE as u32);
-                write_bit = 9;
-                size = 258;
+            };
+            let last = i + 1 == cur.len();
+            if (growth == Growth::Lzap || last) && self.code[node as usize].is_none() {
+                let Some(code) = codes.next() else {
+                    return;
+                };
+                self.code[node as usize] = Some(code);
+                self.node.push(node);
             }
+        }
+    }
+}
 
-            I = Some(c as u32);
+fn encode_phrases(mut reader: impl Read, ds: &mut BitWriter<impl Write>, opts: Options) -> Result<()> {
+    let mut trie = Trie::new();
+    let mut codes = Codes::new(opts);
+    let mut clearing = Clearing::new(opts.policy);
+    // попереднє слово: (вузол, довжина)
+    let mut prev: Option<(u32, usize)> = None;
+    // найдовше слово шукається із забіганням уперед, тому в буфері після pos
+    // тримається не менше max_len байтів (або все до кінця входу), де max_len -
+    // верхня межа довжини слів у дереві
+    let mut max_len = 1;
+    let mut buf = Vec::new();
+    let mut pos = 0;
+    let mut eof = false;
+    loop {
+        if !eof && buf.len() - pos < max_len {
+            buf.drain(..pos);
+            pos = 0;
+            let want = max_len + INPUT_BUF;
+            (&mut reader).take((want - buf.len()) as u64).read_to_end(&mut buf)?;
+            eof = buf.len() < want;
+        }
+        if pos == buf.len() {
+            break;
+        }
+
+        let (node, len) = trie.longest(&buf[pos..]);
+        let code = trie.code[node as usize].unwrap();
+        ds.write_bits(code as u64, codes.bits)?;
+        clearing.in_bytes += len as u64;
+        clearing.out_bits += codes.bits as u64;
+
+        if let Some((prev, prev_len)) = prev {
+            trie.grow(opts.growth, prev, &buf[pos..pos + len], &mut codes);
+            max_len = max_len.max(prev_len + len);
+        }
+        prev = Some((node, len));
+        pos += len;
+
+        if clearing.check(&codes) {
+            ds.write_bits(CLEAR_CODE as u64, codes.bits)?;
+            trie.reset();
+            codes.reset();
+            prev = None;
+            max_len = 1;
         }
     }
-    // print!("({:?},{write_bit},{}) ", I.unwrap(), dict.len());
-    ds.write_bit_sequence(&I.unwrap().to_le_bytes(), write_bit);
-    ds.write_bit_sequence(&END_CODE.to_le_bytes(), write_bit);
+    ds.write_bits(END_CODE as u64, codes.bits)
+}
 
-    ds.close();
-    Ok(())
+// Слово коду code у word (задом наперед, потім розвертається).
+fn get_word(mut code: u32, dict: &[(u32, u8)], word: &mut Vec<u8>) {
+    word.clear();
+    while code >= FIRST_CODE {
+        let (prefix, c) = dict[(code - FIRST_CODE) as usize];
+        word.push(c);
+        code = prefix;
+    }
+    word.push(code as u8);
+    word.reverse();
 }
 
-fn from_le_bytes_to_u32(seq: &[u8]) -> u32 {
-    seq.iter()
-        .enumerate()
-        .fold(0u32, |acc, (i, &x)| acc | ((x as u32) << (8 * i)))
+// Помилки пошкодженого потоку; повертаються всередині io::Error
+// (ErrorKind::InvalidData або UnexpectedEof), їх можна дістати через get_ref().
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DecodeError {
+    // Немає заголовка "LZW" або він містить недопустимі параметри.
+    Header(&'static str),
+    // Код, якого ще немає у словнику.
+    BadCode { code: u32, next: u32 },
+    // Потік закінчився раніше, ніж END_CODE.
+    Truncated,
+    // Потік закодовано словником id, а передано інший (given) або жодного (0).
+    Dictionary { id: u32, given: u32 },
 }
 
-fn get_word(S: &(u8, Option<usize>), dict: &[(u8, Option<usize>)]) -> Vec<u8> {
-    let mut out_S: Vec<u8> = Vec::new();
-    let mut S = S;
-    while let Some(i) = S.1 {
-        out_S.push(S.0);
-        S = &dict[i];
+impl std::fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DecodeError::Header(msg) => write!(f, "LZW: {msg}"),
+            DecodeError::BadCode { code, next } => {
+                write!(f, "LZW: код {code} відсутній у словнику (наступний вільний {next})")
+            }
+            DecodeError::Truncated => write!(f, "LZW: потік обірвався до END_CODE"),
+            DecodeError::Dictionary { id, given: 0 } => {
+                write!(f, "LZW: для декодування потрібен словник {id:08x}")
+            }
+            DecodeError::Dictionary { id, given } => {
+                write!(f, "LZW: потік закодовано словником {id:08x}, а передано {given:08x}")
+            }
+        }
     }
-    out_S.push(S.0);
-    out_S.reverse();
+}
+
+impl std::error::Error for DecodeError {}
 
-    out_S
+impl From<DecodeError> for Error {
+    fn from(e: DecodeError) -> Self {
+        let kind = match e {
+            DecodeError::Truncated => ErrorKind::UnexpectedEof,
+            _ => ErrorKind::InvalidData,
+        };
+        Error::new(kind, e)
+    }
 }
 
 pub fn decode(file_read: &str, file_write: &str) -> Result<()> {
+    let reader = File::open(file_read)?;
     let mut writer = BufWriter::new(File::create(file_write)?);
+    decode_stream(reader, &mut writer)?;
+    writer.flush()
+}
 
-    let mut dict: Vec<(u8, Option<usize>)> = Vec::with_capacity(256);
-
-    for i in 0..=255 {
-        dict.push((i, None));
-    }
+#[allow(dead_code)]
+pub fn decode_bytes(data: &[u8]) -> Result<Vec<u8>> {
+    let mut out = Vec::new();
+    decode_stream(data, &mut out)?;
+    Ok(out)
+}
 
-    dict.push((0, Some(CLEAR_CODE)));
-    dict.push((0, Some(END_CODE)));
+pub fn decode_with_dict(file_read: &str, file_write: &str, dict: &Dictionary) -> Result<()> {
+    let reader = File::open(file_read)?;
+    let mut writer = BufWriter::new(File::create(file_write)?);
+    decode_stream_with(reader, &mut writer, Some(dict))?;
+    writer.flush()
+}
 
-    let mut read_bits = 9;
-    let mut ds = BitStream::new(file_read, Mode::Read);
-    let mut I = from_le_bytes_to_u32(&ds.read_bit_sequence(read_bits)?) as usize;
+pub fn decode_stream(reader: impl Read, writer: impl Write) -> Result<()> {
+    decode_stream_with(reader, writer, None)
+}
 
-    let mut S = dict[I];
-    writer.write_all(&[S.0]); // write S into
+// Декодування потоку, закодованого словником dict; потік без словника
+// декодується як звичайно, хоч би який dict передано.
+pub fn decode_stream_with(
+    mut reader: impl Read,
+    mut writer: impl Write,
+    primed: Option<&Dictionary>,
+) -> Result<()> {
+    let (opts, dict_id) = Options::read_header(&mut reader)?;
+    let words = match primed {
+        _ if dict_id == 0 => &[][..],
+        Some(primed) if primed.id == dict_id => {
+            if opts.growth != Growth::Lzw || primed.check(opts).is_err() {
+                return Err(DecodeError::Header("словник не підходить до параметрів потоку").into());
+            }
+            &primed.words[..]
+        }
+        _ => {
+            let given = primed.map_or(0, |d| d.id);
+            return Err(DecodeError::Dictionary { id: dict_id, given }.into());
+        }
+    };
+    let mut ds = BitReader::new(reader);
+    if opts.growth != Growth::Lzw {
+        return decode_phrases(&mut ds, writer, opts);
+    }
 
-    let (mut old_I, mut old_S) = (I, vec![S.0]);
-    let mut size = dict.len();
-    let mut mm = 0;
-    while let Ok(seq) = ds.read_bit_sequence(read_bits) {
-        I = from_le_bytes_to_u32(&seq) as usize;
+    // слова з кодами від FIRST_CODE; останнє може чекати свого байта
+    let mut dict: Vec<(u32, u8)> = words.to_vec();
+    let mut codes = Codes::primed(opts, words.len());
+    let mut word = Vec::new();
+    // код нового слова, у якого ще невідомий останній байт
+    let mut pending: Option<u32> = None;
+    loop {
+        let I = read_code(&mut ds, codes.bits)?;
         if I == CLEAR_CODE {
-            dict = Vec::new();
-            for i in 0..=255 {
-                dict.push((i, None));
-            }
-            dict.push((0, None));
-            dict.push((0, None));
-            size = dict.len();
-            read_bits = 9;
+            dict.truncate(words.len());
+            codes.reset();
+            pending = None;
+            continue;
+        }
+        if I == END_CODE {
+            break;
+        }
+
+        if pending == Some(I) {
+            // KwKwK: слово = попереднє слово + його перший байт
+            word.push(word[0]);
+        } else if I < CLEAR_CODE || (I >= FIRST_CODE && I < FIRST_CODE + dict.len() as u32) {
+            get_word(I, &dict, &mut word);
+        } else {
+            return Err(DecodeError::BadCode { code: I, next: codes.size }.into());
+        }
+        writer.write_all(&word)?;
 
-            I = from_le_bytes_to_u32(&ds.read_bit_sequence(read_bits)?) as usize;
+        if let Some(code) = pending {
+            dict[(code - FIRST_CODE) as usize].1 = word[0];
+        }
+        pending = codes.next();
+        if pending.is_some() {
+            dict.push((I, 0));
+        }
+    }
 
-            S = dict[I];
-            writer.write_all(&[S.0]);
+    Ok(())
+}
 
-            old_I = I;
-            old_S = vec![S.0];
+fn read_code(ds: &mut BitReader<impl Read>, bits: usize) -> Result<u32> {
+    match ds.read_bits(bits) {
+        Ok(code) => Ok(code as u32),
+        Err(e) if e.kind() == ErrorKind::UnexpectedEof => Err(DecodeError::Truncated.into()),
+        Err(e) => Err(e),
+    }
+}
 
+fn decode_phrases(ds: &mut BitReader<impl Read>, mut writer: impl Write, opts: Options) -> Result<()> {
+    let mut trie = Trie::new();
+    let mut codes = Codes::new(opts);
+    let mut word = Vec::new();
+    let mut prev: Option<u32> = None;
+    loop {
+        let code = read_code(ds, codes.bits)?;
+        if code == CLEAR_CODE {
+            trie.reset();
+            codes.reset();
+            prev = None;
             continue;
         }
-        if I == END_CODE {
-            // println!("END_CODE");
+        if code == END_CODE {
             break;
         }
 
-        if I < size {
-            S = dict[I];
+        // слово додається лише після наступного коду, тож KwKwK тут немає
+        let Some(node) = trie.node_of(code) else {
+            return Err(DecodeError::BadCode { code, next: codes.size }.into());
+        };
+        trie.word(node, &mut word);
+        writer.write_all(&word)?;
+
+        if let Some(prev) = prev {
+            trie.grow(opts.growth, prev, &word, &mut codes);
+        }
+        prev = Some(node);
+    }
+    Ok(())
+}
+
+// Навчений словник LZW, як словники zstd: слова, які кодер і декодер мають
+// від самого початку (і після кожного CLEAR_CODE), з кодами від FIRST_CODE.
+// Слова зберігаються як (префікс, байт), тож кожен префікс слова теж у словнику.
+// ID - хеш FNV-1a від слів, пишеться в заголовок потоку.
+// Файл словника: "LZD", версія, u32 ID, u32 кількість слів, далі слова
+// (u32 префікс, байт).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Dictionary {
+    id: u32,
+    words: Vec<(u32, u8)>,
+}
+
+const DICT_MAGIC: &[u8; 3] = b"LZD";
+const DICT_VERSION: u8 = 1;
+// Скільки кандидатів (у разах від розміру словника) набирається під час навчання.
+const TRAIN_CANDIDATES: usize = 8;
+
+impl Dictionary {
+    // Проганяє LZW по зразках (словник спільний, слово починається заново на
+    // кожному файлі) і лишає до max_words слів, що заощадили найбільше байтів,
+    // разом з їхніми префіксами.
+    pub fn train(samples: &[&str], max_words: usize) -> Result<Self> {
+        let limit = (1 << MAX_BITS) - FIRST_CODE as usize - 1;
+        if !(1..=limit).contains(&max_words) {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("LZW: словник має містити від 1 до {limit} слів"),
+            ));
+        }
+
+        let capacity = (max_words * TRAIN_CANDIDATES).min(limit);
+        let bits = (usize::BITS - (FIRST_CODE as usize + capacity).leading_zeros()) as usize;
+        let mut table = Table::new(bits);
+        // кандидати з кодами від FIRST_CODE, їхня довжина і скільки разів їх видано
+        let mut words: Vec<(u32, u8)> = Vec::new();
+        let mut lens: Vec<u32> = Vec::new();
+        let mut uses: Vec<u64> = Vec::new();
+        let mut data = Vec::new();
+        for sample in samples {
+            data.clear();
+            File::open(sample)?.read_to_end(&mut data)?;
+            let Some((&first, rest)) = data.split_first() else {
+                continue;
+            };
+            let mut I = first as u32;
+            let mut h = Table::start(first);
+            for &c in rest {
+                let next = Table::step(h, c);
+                let slot = match table.find(I, c, next) {
+                    Ok(code) => {
+                        I = code;
+                        h = next;
+                        continue;
+                    }
+                    Err(slot) => slot,
+                };
+                let len = if I >= FIRST_CODE {
+                    uses[(I - FIRST_CODE) as usize] += 1;
+                    lens[(I - FIRST_CODE) as usize]
+                } else {
+                    1
+                };
+                if words.len() < capacity {
+                    table.insert_at(slot, I, c, FIRST_CODE + words.len() as u32);
+                    words.push((I, c));
+                    lens.push(len + 1);
+                    uses.push(0);
+                }
+                I = c as u32;
+                h = Table::start(c);
+            }
+            if I >= FIRST_CODE {
+                uses[(I - FIRST_CODE) as usize] += 1;
+            }
+        }
+
+        // слово довжини len замінює len однобайтових кодів
+        let mut order: Vec<usize> = (0..words.len()).filter(|&i| uses[i] > 0).collect();
+        order.sort_by_key(|&i| std::cmp::Reverse(uses[i] * (lens[i] as u64 - 1)));
+        let mut selected = vec![false; words.len()];
+        let mut count = 0;
+        let mut chain = Vec::new();
+        for i in order {
+            chain.clear();
+            let mut code = FIRST_CODE + i as u32;
+            while code >= FIRST_CODE && !selected[(code - FIRST_CODE) as usize] {
+                chain.push((code - FIRST_CODE) as usize);
+                code = words[(code - FIRST_CODE) as usize].0;
+            }
+            if count + chain.len() > max_words {
+                continue;
+            }
+            for &j in &chain {
+                selected[j] = true;
+            }
+            count += chain.len();
+            if count == max_words {
+                break;
+            }
+        }
+
+        // префікс завжди має менший код, тож порядок кандидатів зберігається
+        let mut renumber = vec![0u32; words.len()];
+        let mut primed = Vec::with_capacity(count);
+        for (i, &(prefix, c)) in words.iter().enumerate() {
+            if selected[i] {
+                renumber[i] = FIRST_CODE + primed.len() as u32;
+                let prefix = if prefix >= FIRST_CODE {
+                    renumber[(prefix - FIRST_CODE) as usize]
+                } else {
+                    prefix
+                };
+                primed.push((prefix, c));
+            }
+        }
+        Ok(Self::from_words(primed))
+    }
+
+    fn from_words(words: Vec<(u32, u8)>) -> Self {
+        // FNV-1a; 0 у заголовку означає потік без словника
+        let mut id: u32 = 0x811c_9dc5;
+        for &(prefix, c) in &words {
+            for b in prefix.to_le_bytes().into_iter().chain([c]) {
+                id = (id ^ b as u32).wrapping_mul(0x0100_0193);
+            }
+        }
+        Self {
+            id: id.max(1),
+            words,
+        }
+    }
+
+    pub fn id(&self) -> u32 {
+        self.id
+    }
+
+    pub fn len(&self) -> usize {
+        self.words.len()
+    }
+
+    // Словник разом з новими словами має вміститися в коди ширини max_bits.
+    fn check(&self, opts: Options) -> Result<()> {
+        if FIRST_CODE as usize + self.words.len() >= 1 << opts.max_bits {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!(
+                    "LZW: словник з {} слів не вміщається в {}-бітні коди",
+                    self.words.len(),
+                    opts.max_bits
+                ),
+            ));
+        }
+        Ok(())
+    }
+
+    pub fn save(&self, file_write: &str) -> Result<()> {
+        let mut writer = BufWriter::new(File::create(file_write)?);
+        writer.write_all(DICT_MAGIC)?;
+        writer.write_all(&[DICT_VERSION])?;
+        writer.write_all(&self.id.to_le_bytes())?;
+        writer.write_all(&(self.words.len() as u32).to_le_bytes())?;
+        for &(prefix, c) in &self.words {
+            writer.write_all(&prefix.to_le_bytes())?;
+            writer.write_all(&[c])?;
+        }
+        writer.flush()
+    }
+
+    pub fn load(file_read: &str) -> Result<Self> {
+        let mut data = Vec::new();
+        File::open(file_read)?.read_to_end(&mut data)?;
+        let bad = |msg: &str| Error::new(ErrorKind::InvalidData, format!("LZW: {msg}"));
+        if data.len() < 12 || &data[..3] != DICT_MAGIC || data[3] != DICT_VERSION {
+            return Err(bad("невідомий формат словника"));
+        }
+        let id = u32::from_le_bytes(data[4..8].try_into().unwrap());
+        let count = u32::from_le_bytes(data[8..12].try_into().unwrap()) as usize;
+        let entries = &data[12..];
+        if entries.len() != count * 5 {
+            return Err(bad("розмір словника не відповідає кількості слів"));
+        }
+        let mut words = Vec::with_capacity(count);
+        for (i, entry) in entries.chunks_exact(5).enumerate() {
+            let prefix = u32::from_le_bytes(entry[..4].try_into().unwrap());
+            if !(prefix < CLEAR_CODE || (FIRST_CODE..FIRST_CODE + i as u32).contains(&prefix)) {
+                return Err(bad("слово словника посилається на відсутній префікс"));
+            }
+            words.push((prefix, entry[4]));
+        }
+        let dict = Self::from_words(words);
+        if dict.id != id {
+            return Err(bad("ID словника не збігається з його вмістом"));
+        }
+        Ok(dict)
+    }
+}
+
+// Формат Unix compress (.Z): магія 1F 9D, байт прапорців (максимальна ширина
+// коду + режим блоків), коди від 9 біт без END_CODE. Коди пишуться групами по
+// вісім (n_bits байт); при зміні ширини неповна група доповнюється до кінця,
+// бо оригінальний декодер читає теж цілими групами.
+const Z_MAGIC: [u8; 2] = [0x1f, 0x9d];
+const Z_BITS_MASK: u8 = 0x1f;
+const Z_BLOCK_MODE: u8 = 0x80;
+const Z_INIT_BITS: usize = 9;
+pub const Z_MAX_BITS: usize = 16;
 
-            old_S = get_word(&S, &dict);
+// Ширина коду і найбільший код, після якого вона зростає; спільне для
+// кодера і декодера. Як і в compress, maxcode на максимальній ширині
+// дорівнює 1 << max_bits, а на старті завжди 511 - тому при -b9 коди
+// все одно переходять на 10 біт.
+struct ZWidth {
+    n_bits: usize,
+    max_code: u32,
+    max_bits: usize,
+}
 
-            writer.write_all(&old_S);
+impl ZWidth {
+    fn new(max_bits: usize) -> Self {
+        Self {
+            n_bits: Z_INIT_BITS,
+            max_code: (1 << Z_INIT_BITS) - 1,
+            max_bits,
+        }
+    }
 
-            dict.push((old_S[0], Some(old_I)));
+    fn reset(&mut self) {
+        *self = Self::new(self.max_bits);
+    }
 
-            old_I = I;
+    fn grow(&mut self) {
+        self.n_bits += 1;
+        self.max_code = if self.n_bits == self.max_bits {
+            1 << self.max_bits
         } else {
-            old_S.push(old_S[0]);
-            writer.write_all(&old_S);
-            dict.push((old_S[0], Some(old_I)));
-            old_I = I;
+            (1 << self.n_bits) - 1
+        };
+    }
+}
+
+struct ZWriter<W: Write> {
+    ds: BitWriter<W>,
+    width: ZWidth,
+    // кодів у поточній групі
+    in_group: usize,
+    bytes_out: u64,
+}
+
+impl<W: Write> ZWriter<W> {
+    fn write(&mut self, code: u32) -> Result<()> {
+        self.ds.write_bits(code as u64, self.width.n_bits)?;
+        self.in_group = (self.in_group + 1) % 8;
+        if self.in_group == 0 {
+            self.bytes_out += self.width.n_bits as u64;
         }
+        Ok(())
+    }
 
-        size += 1;
-        if size + 1 == (1 << read_bits) {
-            read_bits += 1;
+    // Доповнює неповну групу нулями перед зміною ширини.
+    fn pad_group(&mut self) -> Result<()> {
+        if self.in_group != 0 {
+            let mut pad = (8 - self.in_group) * self.width.n_bits;
+            while pad > 0 {
+                let n = pad.min(BitWriter::<W>::MAX_WRITE);
+                self.ds.write_bits(0, n)?;
+                pad -= n;
+            }
+            self.bytes_out += self.width.n_bits as u64;
+            self.in_group = 0;
         }
+        Ok(())
+    }
+}
+
+pub fn encode_z(file_read: &str, file_write: &str, max_bits: usize) -> Result<()> {
+    let reader = File::open(file_read)?;
+    let writer = File::create(file_write)?;
+    encode_z_stream(reader, writer, max_bits)
+}
+
+pub fn encode_z_stream(reader: impl Read, mut writer: impl Write, max_bits: usize) -> Result<()> {
+    if !(Z_INIT_BITS..=Z_MAX_BITS).contains(&max_bits) {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!("LZW: ширина коду .Z має бути в межах {Z_INIT_BITS}..={Z_MAX_BITS} біт"),
+        ));
     }
+    writer.write_all(&Z_MAGIC)?;
+    writer.write_all(&[max_bits as u8 | Z_BLOCK_MODE])?;
+    let mut zw = ZWriter {
+        ds: BitWriter::new(writer),
+        width: ZWidth::new(max_bits),
+        in_group: 0,
+        bytes_out: 3,
+    };
+
+    // у режимі блоків код 256 - CLEAR, нові слова з 257
+    let first = CLEAR_CODE + 1;
+    let max_max_code = 1u32 << max_bits;
+    let mut dict: HashMap<(u32, u8), u32> = HashMap::new();
+    let mut free_ent = first;
+    let mut ent: Option<u32> = None;
+    let (mut in_count, mut checkpoint, mut ratio) = (0u64, CHECK_GAP, 0u64);
+    for c in BufReader::new(reader).bytes() {
+        let c = c?;
+        in_count += 1;
+        let Some(prefix) = ent else {
+            ent = Some(c as u32);
+            continue;
+        };
+        if let Some(&code) = dict.get(&(prefix, c)) {
+            ent = Some(code);
+            continue;
+        }
 
-    writer.flush()?;
+        zw.write(prefix)?;
+        // як у compress: ширина росте до додавання нового слова
+        if free_ent > zw.width.max_code {
+            zw.pad_group()?;
+            zw.width.grow();
+        }
+        ent = Some(c as u32);
 
+        if free_ent < max_max_code {
+            dict.insert((prefix, c), free_ent);
+            free_ent += 1;
+        } else if in_count >= checkpoint {
+            // словник заповнений: скидаємо його, коли стиснення погіршується
+            // ціле відношення, як cl_block() у compress, щоб CLEAR ішов там само
+            checkpoint = in_count + CHECK_GAP;
+            let current = if in_count > 0x007f_ffff {
+                match zw.bytes_out >> 8 {
+                    0 => 0x7fff_ffff,
+                    out => in_count / out,
+                }
+            } else {
+                (in_count << 8) / zw.bytes_out
+            };
+            if current > ratio {
+                ratio = current;
+            } else {
+                ratio = 0;
+                dict.clear();
+                free_ent = first;
+                zw.write(CLEAR_CODE)?;
+                zw.pad_group()?;
+                zw.width.reset();
+            }
+        }
+    }
+    if let Some(prefix) = ent {
+        zw.write(prefix)?;
+    }
+    zw.ds.finish()?;
     Ok(())
 }
 
-fn fun_mtf(types: &str, num: &str) -> Result<()> {
-    let test_path = "test_files_mtf/".to_string() + types + "/test" + num + ".mtf";
-    let test_path_out = "test_files/".to_string() + types + "/test" + num + ".mlzw";
-    // let test_path_decode = "test_files/".to_string() + types + "/test" + num + ".decmtf";
-    encode(&test_path, &test_path_out)?;
-    println!("Encoded: {types}.{num}");
-    // decode(&test_path_out, &test_path_decode)?;
-    // println!("Decoded");
-    Ok(())
+// Читання кодів групами, як getcode() у compress.
+struct ZReader<R: Read> {
+    reader: R,
+    buf: [u8; Z_MAX_BITS],
+    // позиція і кількість корисних бітів у групі
+    offset: usize,
+    size: usize,
+    width: ZWidth,
+    clear: bool,
 }
 
-fn fun_bwt(types: &str, num: &str) -> Result<()> {
-    let test_path = "test_files_bwt/".to_string() + types + "/test" + num + ".bwt";
-    let test_path_out = "test_files/".to_string() + types + "/test" + num + ".blzw";
-    // let test_path_decode = "test_files/".to_string() + types + "/test" + num + ".decbwt";
-    encode(&test_path, &test_path_out)?;
-    println!("Encoded: {types}.{num}");
-    // decode(&test_path_out, &test_path_decode)?;
-    // println!("Decoded");
-    Ok(())
+impl<R: Read> ZReader<R> {
+    fn code(&mut self, free_ent: u32) -> Result<Option<u32>> {
+        let grow = free_ent > self.width.max_code;
+        if self.clear || self.offset >= self.size || grow {
+            if grow {
+                self.width.grow();
+            }
+            if self.clear {
+                self.width.reset();
+                self.clear = false;
+            }
+            let n_bits = self.width.n_bits;
+            let mut len = 0;
+            while len < n_bits {
+                match self.reader.read(&mut self.buf[len..n_bits]) {
+                    Ok(0) => break,
+                    Ok(n) => len += n,
+                    Err(e) if e.kind() == ErrorKind::Interrupted => {}
+                    Err(e) => return Err(e),
+                }
+            }
+            if len == 0 {
+                return Ok(None);
+            }
+            self.offset = 0;
+            // останній код групи мусить поміститися цілком
+            self.size = (len * 8).saturating_sub(n_bits - 1);
+            if self.size == 0 {
+                return Ok(None);
+            }
+        }
+
+        let mut code = 0u32;
+        for i in 0..self.width.n_bits {
+            let bit = self.offset + i;
+            code |= ((self.buf[bit / 8] >> (bit % 8)) as u32 & 1) << i;
+        }
+        self.offset += self.width.n_bits;
+        Ok(Some(code))
+    }
 }
 
-fn fun_bwtmtf(types: &str, num: &str) -> Result<()> {
-    let test_path = "test_files_bwtmtf/".to_string() + types + "/test" + num + ".bwtmtf";
-    let test_path_out = "test_files/".to_string() + types + "/test" + num + ".bmlzw";
-    // let test_path_decode = "test_files_csv/".to_string() + types + "/test" + num + ".dbw";
-    encode(&test_path, &test_path_out)?;
-    println!("Encoded: {types}.{num}");
-    // decode(&test_path_out, &test_path_decode)?;
-    // println!("Decoded");
-    Ok(())
+pub fn decode_z(file_read: &str, file_write: &str) -> Result<()> {
+    let reader = BufReader::new(File::open(file_read)?);
+    let mut writer = BufWriter::new(File::create(file_write)?);
+    decode_z_stream(reader, &mut writer)?;
+    writer.flush()
 }
 
-fn test_bwt(types: &str) -> Result<()> {
-    println!("type of file: {}", types);
-    fun_bwt(types, "1")?;
-    fun_bwt(types, "2")?;
-    fun_bwt(types, "3")?;
-    fun_bwt(types, "4")?;
-    fun_bwt(types, "5")?;
-    fun_bwt(types, "6")?;
-    fun_bwt(types, "7")?;
-    fun_bwt(types, "8")?;
-    fun_bwt(types, "9")?;
-    fun_bwt(types, "10")?;
+pub fn decode_z_stream(mut reader: impl Read, mut writer: impl Write) -> Result<()> {
+    let mut header = [0u8; 3];
+    reader.read_exact(&mut header).map_err(|e| match e.kind() {
+        ErrorKind::UnexpectedEof => DecodeError::Truncated.into(),
+        _ => e,
+    })?;
+    if header[..2] != Z_MAGIC {
+        return Err(DecodeError::Header("немає сигнатури .Z").into());
+    }
+    let max_bits = (header[2] & Z_BITS_MASK) as usize;
+    let block_mode = header[2] & Z_BLOCK_MODE != 0;
+    if !(Z_INIT_BITS..=Z_MAX_BITS).contains(&max_bits) {
+        return Err(DecodeError::Header("недопустима ширина коду").into());
+    }
+
+    let max_max_code = 1u32 << max_bits;
+    let first = if block_mode { CLEAR_CODE + 1 } else { CLEAR_CODE };
+    let mut zr = ZReader {
+        reader,
+        buf: [0; Z_MAX_BITS],
+        offset: 0,
+        size: 0,
+        width: ZWidth::new(max_bits),
+        clear: false,
+    };
+
+    // (префікс, останній байт) для кодів від 256
+    let mut dict: Vec<(u32, u8)> = vec![(0, 0); (max_max_code - CLEAR_CODE) as usize];
+    let mut free_ent = first;
+    let mut word = Vec::new();
+
+    let Some(mut old_code) = zr.code(free_ent)? else {
+        return Ok(());
+    };
+    if old_code >= CLEAR_CODE {
+        return Err(DecodeError::BadCode { code: old_code, next: free_ent }.into());
+    }
+    let mut fin_char = old_code as u8;
+    writer.write_all(&[fin_char])?;
+
+    while let Some(mut code) = zr.code(free_ent)? {
+        if code == CLEAR_CODE && block_mode {
+            zr.clear = true;
+            // наступний код додасть фіктивне слово 256, як в оригіналі
+            free_ent = first - 1;
+            match zr.code(free_ent)? {
+                Some(next) if next < CLEAR_CODE => code = next,
+                Some(next) => return Err(DecodeError::BadCode { code: next, next: free_ent }.into()),
+                None => break,
+            }
+        }
+
+        let in_code = code;
+        word.clear();
+        if code >= free_ent {
+            // KwKwK
+            if code > free_ent {
+                return Err(DecodeError::BadCode { code, next: free_ent }.into());
+            }
+            word.push(fin_char);
+            code = old_code;
+        }
+        while code >= CLEAR_CODE {
+            let (prefix, c) = dict[(code - CLEAR_CODE) as usize];
+            word.push(c);
+            code = prefix;
+        }
+        fin_char = code as u8;
+        word.push(fin_char);
+        word.reverse();
+        writer.write_all(&word)?;
+
+        if free_ent < max_max_code {
+            dict[(free_ent - CLEAR_CODE) as usize] = (old_code, fin_char);
+            free_ent += 1;
+        }
+        old_code = in_code;
+    }
+
     Ok(())
 }
 
-fn test_mtf(types: &str) -> Result<()> {
-    println!("type of file: {}", types);
-    fun_mtf(types, "1")?;
-    fun_mtf(types, "2")?;
-    fun_mtf(types, "3")?;
-    fun_mtf(types, "4")?;
-    fun_mtf(types, "5")?;
-    fun_mtf(types, "6")?;
-    fun_mtf(types, "7")?;
-    fun_mtf(types, "8")?;
-    fun_mtf(types, "9")?;
-    fun_mtf(types, "10")?;
-    Ok(())
+// LZW з GIF: перший байт - мінімальний розмір коду n (2..=8), далі коди
+// шириною від n + 1 до 12 біт (молодший біт першим), нарізані на підблоки
+// по 255 байт з байтом довжини попереду і нульовим блоком у кінці.
+// CLEAR = 2^n, END = 2^n + 1. Кодер очищує словник, щойно той заповнився,
+// а декодер приймає й відкладене очищення: на 4096 словах він просто перестає
+// додавати нові слова, доки не прийде CLEAR. CLI цей формат не використовує.
+const GIF_MAX_BITS: usize = 12;
+const GIF_BLOCK: usize = 255;
+
+fn gif_code_size(min_code_size: u8) -> Result<usize> {
+    if !(2..=8).contains(&min_code_size) {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!("LZW: розмір коду GIF {min_code_size} поза межами 2..=8"),
+        ));
+    }
+    Ok(min_code_size as usize)
 }
 
-fn test_bwtmtf(types: &str) -> Result<()> {
-    println!("type of file: {}", types);
-    fun_bwtmtf(types, "1")?;
-    fun_bwtmtf(types, "2")?;
-    fun_bwtmtf(types, "3")?;
-    fun_bwtmtf(types, "4")?;
-    fun_bwtmtf(types, "5")?;
-    fun_bwtmtf(types, "6")?;
-    fun_bwtmtf(types, "7")?;
-    fun_bwtmtf(types, "8")?;
-    fun_bwtmtf(types, "9")?;
-    fun_bwtmtf(types, "10")?;
-    Ok(())
+#[allow(dead_code)]
+pub fn encode_gif(data: &[u8], min_code_size: u8) -> Result<Vec<u8>> {
+    let n = gif_code_size(min_code_size)?;
+    let clear = 1u32 << n;
+    let end = clear + 1;
+    if let Some(&c) = data.iter().find(|&&c| c as u32 >= clear) {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!("LZW: індекс {c} не вміщується в {n} біт"),
+        ));
+    }
+
+    let mut ds = BitWriter::new(Vec::new());
+    let mut dict: HashMap<(u32, u8), u32> = HashMap::new();
+    let mut next_code = clear + 2;
+    let mut bits = n + 1;
+    ds.write_bits(clear as u64, bits)?;
+
+    let mut ent: Option<u32> = None;
+    // чи був код після останнього CLEAR: декодер додає слово лише з другого
+    let mut fresh = true;
+    for &c in data {
+        let Some(prefix) = ent else {
+            ent = Some(c as u32);
+            continue;
+        };
+        if let Some(&code) = dict.get(&(prefix, c)) {
+            ent = Some(code);
+            continue;
+        }
+
+        ds.write_bits(prefix as u64, bits)?;
+        fresh = false;
+        dict.insert((prefix, c), next_code);
+        next_code += 1;
+        // декодер дізнається про слово на код пізніше, тому ширина росте,
+        // коли next_code перевищує 2^bits, а не досягає
+        if next_code > 1 << bits && bits < GIF_MAX_BITS {
+            bits += 1;
+        }
+        if next_code == 1 << GIF_MAX_BITS {
+            ds.write_bits(clear as u64, bits)?;
+            dict.clear();
+            next_code = clear + 2;
+            bits = n + 1;
+            fresh = true;
+        }
+        ent = Some(c as u32);
+    }
+    if let Some(prefix) = ent {
+        ds.write_bits(prefix as u64, bits)?;
+        // останній код теж додає слово у декодері, і END може бути ширшим
+        if !fresh {
+            next_code += 1;
+            if next_code > 1 << bits && bits < GIF_MAX_BITS {
+                bits += 1;
+            }
+        }
+    }
+    ds.write_bits(end as u64, bits)?;
+    let packed = ds.finish()?;
+
+    let mut out = Vec::with_capacity(packed.len() + packed.len() / GIF_BLOCK + 3);
+    out.push(min_code_size);
+    for block in packed.chunks(GIF_BLOCK) {
+        out.push(block.len() as u8);
+        out.extend_from_slice(block);
+    }
+    out.push(0);
+    Ok(out)
 }
 
-use rayon::prelude::*;
-use rayon::scope;
-
-fn run_all_tests() -> Result<()> {
-    println!("BWT");
-    let files = ["pdf", "mov", "3mf", "exe", "csv"];
-    files.par_iter().for_each(|f| {
-        test_bwt(f).unwrap(); // всередині відкривається свій файл і пишеться у нього
-    });
-
-    println!("MTF");
-    files.par_iter().for_each(|f| {
-        test_mtf(f).unwrap(); // всередині відкривається свій файл і пишеться у нього
-    });
-    println!("BWTMTF");
-    files.par_iter().for_each(|f| {
-        test_bwtmtf(f).unwrap(); // всередині відкривається свій файл і пишеться у нього
-    });
-    Ok(())
+#[allow(dead_code)]
+pub fn decode_gif(data: &[u8]) -> Result<Vec<u8>> {
+    let (&min_code_size, mut rest) = data.split_first().ok_or(DecodeError::Truncated)?;
+    let n = gif_code_size(min_code_size)
+        .map_err(|_| DecodeError::Header("недопустимий розмір коду GIF"))?;
+    let clear = 1u32 << n;
+    let end = clear + 1;
+
+    // збираємо підблоки до нульового
+    let mut packed = Vec::with_capacity(rest.len());
+    loop {
+        let (&len, tail) = rest.split_first().ok_or(DecodeError::Truncated)?;
+        if len == 0 {
+            break;
+        }
+        if tail.len() < len as usize {
+            return Err(DecodeError::Truncated.into());
+        }
+        packed.extend_from_slice(&tail[..len as usize]);
+        rest = &tail[len as usize..];
+    }
+
+    let mut ds = BitReader::new(packed.as_slice());
+    // (префікс, останній байт) для кодів від clear + 2
+    let mut dict: Vec<(u32, u8)> = Vec::with_capacity(1 << GIF_MAX_BITS);
+    let mut next_code = clear + 2;
+    let mut bits = n + 1;
+    let mut out = Vec::new();
+    // початок попереднього слова в out
+    let mut prev: Option<(u32, usize)> = None;
+    loop {
+        let code = match ds.read_bits(bits) {
+            Ok(code) => code as u32,
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => return Err(DecodeError::Truncated.into()),
+            Err(e) => return Err(e),
+        };
+        if code == clear {
+            dict.clear();
+            next_code = clear + 2;
+            bits = n + 1;
+            prev = None;
+            continue;
+        }
+        if code == end {
+            break;
+        }
+
+        let start = out.len();
+        match prev {
+            Some((_, prev_start)) if code == next_code && next_code < 1 << GIF_MAX_BITS => {
+                // KwKwK
+                out.extend_from_within(prev_start..start);
+                out.push(out[prev_start]);
+            }
+            _ if code < clear => out.push(code as u8),
+            _ if code > end && code < next_code => {
+                let mut c = code;
+                while c > end {
+                    let (prefix, suffix) = dict[(c - clear - 2) as usize];
+                    out.push(suffix);
+                    c = prefix;
+                }
+                out.push(c as u8);
+                out[start..].reverse();
+            }
+            _ => return Err(DecodeError::BadCode { code, next: next_code }.into()),
+        }
+
+        if let Some((prev_code, _)) = prev
+            && next_code < 1 << GIF_MAX_BITS
+        {
+            dict.push((prev_code, out[start]));
+            next_code += 1;
+            if next_code == 1 << bits && bits < GIF_MAX_BITS {
+                bits += 1;
+            }
+        }
+        prev = Some((code, start));
+    }
+    Ok(out)
 }
 
-fn lens(types: &str, num: &str, algo: &str) -> Result<f32> {
-    let test_path = "test_files/".to_string() + types + "/test" + num + "." + types;
-    let test_path_out = "test_files/".to_string() + types + "/test" + num + "." + algo;
-    let test_path_decode = "test_files/".to_string() + types + "/test" + num + ".dec";
-    let metadata = std::fs::metadata(test_path)?;
-    let size_test = metadata.len();
-    let metadata = std::fs::metadata(test_path_out)?;
-    let arch_test = metadata.len();
-    // println!(
-    //     "[{num}],[{}],[{}],[{}],",
-    //     size_test,
-    //     arch_test,
-    //     size_test as f32 / arch_test as f32
-    // );
-
-    Ok(size_test as f32 / arch_test as f32)
-}
-
-fn calc(s: &mut f32, max_t: &mut f32, min_t: &mut f32, t: f32) {
-    *max_t = max_t.max(t);
-    *min_t = min_t.min(t);
-    *s += t;
-}
-
-fn all_lens(types: &str, algo: &str, cap: &str) {
-    // println!("{}.{}", types, algo);
-    let mut s = 0.0;
-    let mut min_t: f32 = 1000.;
-    let mut max_t: f32 = -1000.;
-    // println!("#figure(\n table(\n columns: 4,\n table.header[*Файл*][*Початкова \\#байт*][*Cтиснута \\#байт*][*коеф*], ");
-    let mut t = lens(types, "1", algo).unwrap();
-    calc(&mut s, &mut max_t, &mut min_t, t);
-    t = lens(types, "2", algo).unwrap();
-    calc(&mut s, &mut max_t, &mut min_t, t);
-    t = lens(types, "3", algo).unwrap();
-    calc(&mut s, &mut max_t, &mut min_t, t);
-    t = lens(types, "4", algo).unwrap();
-    calc(&mut s, &mut max_t, &mut min_t, t);
-    t = lens(types, "5", algo).unwrap();
-    calc(&mut s, &mut max_t, &mut min_t, t);
-    t = lens(types, "6", algo).unwrap();
-    calc(&mut s, &mut max_t, &mut min_t, t);
-    t = lens(types, "7", algo).unwrap();
-    calc(&mut s, &mut max_t, &mut min_t, t);
-    t = lens(types, "8", algo).unwrap();
-    calc(&mut s, &mut max_t, &mut min_t, t);
-    t = lens(types, "9", algo).unwrap();
-    calc(&mut s, &mut max_t, &mut min_t, t);
-    t = lens(types, "10", algo).unwrap();
-    calc(&mut s, &mut max_t, &mut min_t, t);
-    // println!("),\n caption: \"{types} {cap}\",\n)\n");
-
-    println!("[{}],  [{}], [{}],[{}],", types, s / 10., min_t, max_t,);
-}
-
-fn fun_lzw(types: &str, num: &str) -> Result<()> {
-    let test_path = "test_files/".to_string() + types + "/test" + num + "." + types;
-    let test_path_out = "test_files/".to_string() + types + "/test" + num + ".lzw";
-    // let test_path_decode = "test_files/".to_string() + types + "/test" + num + ".decmtf";
-    encode(&test_path, &test_path_out)?;
-    println!("Encoded: {types}.{num}");
-    // decode(&test_path_out, &test_path_decode)?;
-    // println!("Decoded");
+pub fn bench_growth(types: &str) -> Result<()> {
+    println!("type of file: {}", types);
+    for num in 1..=10 {
+        let test_path = format!("test_files/{types}/test{num}.{types}");
+        let test_path_out = format!("test_files/{types}/test{num}.lzwg");
+        let size = std::fs::metadata(&test_path)?.len();
+        print!("[{num}]");
+        for growth in [Growth::Lzw, Growth::Lzmw, Growth::Lzap] {
+            let opts = Options {
+                growth,
+                ..Options::default()
+            };
+            let start = Instant::now();
+            encode_with(&test_path, &test_path_out, opts)?;
+            let elapsed = start.elapsed();
+            let arch = std::fs::metadata(&test_path_out)?.len();
+            print!(", [{:.3}], [{:?}]", size as f64 / arch as f64, elapsed);
+        }
+        println!(",");
+    }
     Ok(())
 }
-fn test_lzw(types: &str) -> Result<()> {
+
+// Словник, навчений на test1..test5, проти звичайного LZW на test6..test10.
+pub fn bench_dict(types: &str) -> Result<()> {
     println!("type of file: {}", types);
-    fun_lzw(types, "1")?;
-    fun_lzw(types, "2")?;
-    fun_lzw(types, "3")?;
-    fun_lzw(types, "4")?;
-    fun_lzw(types, "5")?;
-    fun_lzw(types, "6")?;
-    fun_lzw(types, "7")?;
-    fun_lzw(types, "8")?;
-    fun_lzw(types, "9")?;
-    fun_lzw(types, "10")?;
+    let samples: Vec<String> = (1..=5)
+        .map(|num| format!("test_files/{types}/test{num}.{types}"))
+        .collect();
+    let samples: Vec<&str> = samples.iter().map(String::as_str).collect();
+    let dict = Dictionary::train(&samples, 1 << 16)?;
+    dict.save(&format!("test_files/{types}/{types}.lzd"))?;
+    for num in 6..=10 {
+        let test_path = format!("test_files/{types}/test{num}.{types}");
+        let test_path_out = format!("test_files/{types}/test{num}.lzwd");
+        let size = std::fs::metadata(&test_path)?.len() as f64;
+        encode(&test_path, &test_path_out)?;
+        let plain = std::fs::metadata(&test_path_out)?.len() as f64;
+        encode_with_dict(&test_path, &test_path_out, Options::default(), &dict)?;
+        let primed = std::fs::metadata(&test_path_out)?.len() as f64;
+        println!("[{num}], [{:.3}], [{:.3}],", size / plain, size / primed);
+    }
     Ok(())
 }
 
-fn main() -> Result<()> {
-    let files = ["pdf", "mov", "3mf", "exe", "csv"];
-    // files.par_iter().for_each(|f| {
-    //     test_lzw(f).unwrap(); // всередині відкривається свій файл і пишеться у нього
-    // });
-
-    println!("== LZW");
-    // println!("---- START MOV ----");
-    println!("=== MOV");
-    all_lens("mov", "lzw", "");
-    all_lens("mov", "blzw", "bwt");
-    all_lens("mov", "mlzw", "mtf");
-    all_lens("mov", "bmlzw", "bwt+mtf");
-
-    // println!("---- END MOV ----\n");
-    // println!("---- START PDF ----");
-    println!("=== PDF");
-    all_lens("pdf", "lzw", "");
-    all_lens("pdf", "blzw", "bwt");
-    all_lens("pdf", "mlzw", "mtf");
-    all_lens("pdf", "bmlzw", "bwt+mtf");
-    // println!("---- END PDF ----\n");
-    // println!("---- START CSV ----");
-    println!("=== CSV");
-    all_lens("csv", "lzw", "");
-    all_lens("csv", "blzw", "bwt");
-    all_lens("csv", "mlzw", "mtf");
-    all_lens("csv", "bmlzw", "bwt+mtf");
-
-    // println!("---- END CSV ----\n");
-    // println!("---- START EXE ----");
-
-    println!("=== EXE");
-    all_lens("exe", "lzw", "");
-    all_lens("exe", "blzw", "bwt");
-    all_lens("exe", "mlzw", "mtf");
-    all_lens("exe", "bmlzw", "bwt+mtf");
-    // println!("---- END EXE ----\n");
-    // println!("---- START 3mf ----");
-
-    println!("=== 3MF");
-    all_lens("3mf", "lzw", "");
-    all_lens("3mf", "blzw", "bwt");
-    all_lens("3mf", "mlzw", "mtf");
-    all_lens("3mf", "bmlzw", "bwt+mtf");
-
-    println!("#figure(\n table(\n columns: 4,\n table.header[*Тип файлу*][*Середній коеф*][*Мінімальний*][*Максимальний*], ");
-    all_lens("mov", "lzw", "");
-    all_lens("pdf", "lzw", "");
-    all_lens("csv", "lzw", "");
-    all_lens("exe", "lzw", "");
-    all_lens("3mf", "lzw", "");
-    println!("),\n caption: \"lzw\",\n)\n");
-
-    println!("#figure(\n table(\n columns: 4,\n table.header[*Тип файлу*][*Середній коеф*][*Мінімальний*][*Максимальний*], ");
-    all_lens("mov", "blzw", "bwt");
-    all_lens("pdf", "blzw", "bwt");
-    all_lens("csv", "blzw", "bwt");
-    all_lens("exe", "blzw", "bwt");
-    all_lens("3mf", "blzw", "bwt");
-    println!("),\n caption: \"bwt+lzw\",\n)\n");
-    println!("#figure(\n table(\n columns: 4,\n table.header[*Тип файлу*][*Середній коеф*][*Мінімальний*][*Максимальний*], ");
-    all_lens("mov", "mlzw", "mtf");
-    all_lens("pdf", "mlzw", "mtf");
-    all_lens("csv", "mlzw", "mtf");
-    all_lens("exe", "mlzw", "mtf");
-    all_lens("3mf", "mlzw", "mtf");
-    println!("),\n caption: \"mtf+lzw\",\n)\n");
-    println!("#figure(\n table(\n columns: 4,\n table.header[*Тип файлу*][*Середній коеф*][*Мінімальний*][*Максимальний*], ");
-    all_lens("mov", "bmlzw", "bwt+mtf");
-    all_lens("pdf", "bmlzw", "bwt+mtf");
-    all_lens("csv", "bmlzw", "bwt+mtf");
-    all_lens("exe", "bmlzw", "bwt+mtf");
-    all_lens("3mf", "bmlzw", "bwt+mtf");
-    println!("),\n caption: \" bwt+mtf+lzw\",\n)\n");
-
-    // all_lens("exe", "blzw");
-    // all_lens("exe", "mlzw");
-    // all_lens("exe", "bmlzw");
-    // run_all_tests();
-    // println!("BWT");
-    // test_bwt("pdf")?;
-    // test_bwt("mov")?;
-    // test_bwt("3mf")?;
-    // test_bwt("exe")?;
-    // test_bwt("csv")?;
-    // println!("MTF");
-    // test_mtf("pdf")?;
-    // test_mtf("mov")?;
-    // test_mtf("3mf")?;
-    // test_mtf("exe")?;
-    // test_mtf("csv")?;
-    // println!("BWTMTF");
-    // test_bwtmtf("pdf")?;
-    // test_bwtmtf("mov")?;
-    // test_bwtmtf("3mf")?;
-    // test_bwtmtf("exe")?;
-    // test_bwtmtf("csv")?;
-    Ok(())
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Випадкові байти: спершу з алфавіту з чотирьох літер (довгі слова,
+    // добре стискається), далі з усіх 256 значень.
+    fn sample(skewed: usize, uniform: usize) -> Vec<u8> {
+        let mut x = 12345u32;
+        let mut next = move || {
+            x = x.wrapping_mul(1103515245).wrapping_add(12345);
+            (x >> 16) as u8
+        };
+        let mut data: Vec<u8> = (0..skewed).map(|_| b'a' + next() % 4).collect();
+        data.extend((0..uniform).map(|_| next()));
+        data
+    }
+
+    // Повторює encode_lzw на HashMap: де (у байтах входу) закінчується кожне
+    // видане слово і після скількох слів кодер очищає словник.
+    fn phrases(data: &[u8], opts: Options) -> (Vec<usize>, Vec<usize>) {
+        let mut dict: HashMap<(u32, u8), u32> = HashMap::new();
+        let mut codes = Codes::new(opts);
+        let mut clearing = Clearing::new(opts.policy);
+        let mut ends = Vec::new();
+        let mut clears = Vec::new();
+        let mut I: Option<u32> = None;
+        for (i, &c) in data.iter().enumerate() {
+            clearing.in_bytes += 1;
+            let Some(prefix) = I else {
+                I = Some(c as u32);
+                continue;
+            };
+            if let Some(&code) = dict.get(&(prefix, c)) {
+                I = Some(code);
+                continue;
+            }
+            ends.push(i);
+            clearing.out_bits += codes.bits as u64;
+            if let Some(code) = codes.next() {
+                dict.insert((prefix, c), code);
+            }
+            if clearing.check(&codes) {
+                clears.push(ends.len());
+                dict.clear();
+                codes.reset();
+            }
+            I = Some(c as u32);
+        }
+        (ends, clears)
+    }
+
+    fn round_trip(data: &[u8], opts: Options) {
+        let packed = encode_bytes(data, opts).unwrap();
+        let unpacked = decode_bytes(&packed).unwrap();
+        assert!(unpacked == data, "{opts:?}, {} bytes", data.len());
+    }
+
+    // Префікси входу, з яких кодер видає рівно k - 1, k і k + 1 кодів даних.
+    fn around(data: &[u8], ends: &[usize], k: usize, opts: Options) {
+        for k in [k - 1, k, k + 1] {
+            if let Some(&end) = ends.get(k.wrapping_sub(1)) {
+                round_trip(&data[..end], opts);
+            }
+        }
+    }
+
+    #[test]
+    fn width_and_clear_boundaries() {
+        let short = sample(40_000, 40_000);
+        let long = sample(40_000, 200_000);
+        let first = FIRST_CODE as usize;
+        for (min_bits, max_bits) in [(9, 9), (9, 10), (9, 12), (10, 12), (12, 16)] {
+            let data = if max_bits > 12 { &long } else { &short };
+            for policy in [Policy::Reset, Policy::Freeze, Policy::Ratio] {
+                let opts = Options {
+                    min_bits,
+                    max_bits,
+                    policy,
+                    ..Options::default()
+                };
+                let (ends, clears) = phrases(data, opts);
+                let full = (1 << max_bits) - first;
+                assert!(ends.len() > full + 1, "{opts:?}: замалий вхід");
+                match policy {
+                    Policy::Reset => assert_eq!(clears.first(), Some(&full)),
+                    Policy::Freeze => assert!(clears.is_empty()),
+                    Policy::Ratio if max_bits <= 12 => assert!(!clears.is_empty()),
+                    Policy::Ratio => {}
+                }
+
+                // зміна ширини: після слова, що займає код 2^bits - 1
+                for bits in min_bits..max_bits {
+                    around(data, &ends, (1 << bits) - first, opts);
+                }
+                // словник заповнено: Reset очищає, Freeze і Ratio заморожують
+                around(data, &ends, full, opts);
+                // очищення і перша зміна ширини після нього
+                for &clear in clears.iter().take(2) {
+                    around(data, &ends, clear, opts);
+                    if min_bits < max_bits {
+                        around(data, &ends, clear + (1 << min_bits) - first, opts);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn small_inputs() {
+        for n in 0..600 {
+            round_trip(&sample(n / 2, n - n / 2), Options::default());
+        }
+        round_trip(&[0u8; 100_000], Options::default());
+    }
+
+    #[test]
+    fn phrase_growth_boundaries() {
+        let data = sample(8_000, 8_000);
+        for growth in [Growth::Lzmw, Growth::Lzap] {
+            for (min_bits, max_bits) in [(9, 9), (9, 10), (9, 12)] {
+                for policy in [Policy::Reset, Policy::Freeze, Policy::Ratio] {
+                    let opts = Options {
+                        min_bits,
+                        max_bits,
+                        policy,
+                        growth,
+                    };
+                    // у LZMW/LZAP слова довші, тож межі перебираються довжиною входу
+                    for n in (0..data.len()).step_by(1499).chain([data.len()]) {
+                        round_trip(&data[..n], opts);
+                    }
+                }
+            }
+        }
+    }
+
+    // encode_phrases з усім входом у пам'яті: еталон для потокового кодера.
+    fn encode_phrases_whole(data: &[u8], opts: Options) -> Vec<u8> {
+        let mut out = Vec::new();
+        opts.write_header(&mut out, 0).unwrap();
+        let mut ds = BitWriter::new(out);
+        let mut trie = Trie::new();
+        let mut codes = Codes::new(opts);
+        let mut clearing = Clearing::new(opts.policy);
+        let mut prev = None;
+        let mut pos = 0;
+        while pos < data.len() {
+            let (node, len) = trie.longest(&data[pos..]);
+            ds.write_bits(trie.code[node as usize].unwrap() as u64, codes.bits).unwrap();
+            clearing.in_bytes += len as u64;
+            clearing.out_bits += codes.bits as u64;
+            if let Some(prev) = prev {
+                trie.grow(opts.growth, prev, &data[pos..pos + len], &mut codes);
+            }
+            prev = Some(node);
+            pos += len;
+            if clearing.check(&codes) {
+                ds.write_bits(CLEAR_CODE as u64, codes.bits).unwrap();
+                trie.reset();
+                codes.reset();
+                prev = None;
+            }
+        }
+        ds.write_bits(END_CODE as u64, codes.bits).unwrap();
+        ds.finish().unwrap()
+    }
+
+    #[test]
+    fn streamed_phrases() {
+        // серія нулів дає дуже довгі слова, і одне з них перетинає межу INPUT_BUF
+        let mut data = sample(900_000, 0);
+        data.resize(1_300_000, 0);
+        data.extend(sample(0, 50_000));
+        for growth in [Growth::Lzmw, Growth::Lzap] {
+            for policy in [Policy::Reset, Policy::Freeze] {
+                let opts = Options {
+                    max_bits: 16,
+                    policy,
+                    growth,
+                    ..Options::default()
+                };
+                let packed = encode_bytes(&data, opts).unwrap();
+                assert!(packed == encode_phrases_whole(&data, opts), "{opts:?}");
+                assert!(decode_bytes(&packed).unwrap() == data, "{opts:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn frozen_trie_stops_growing() {
+        let opts = Options {
+            max_bits: 9,
+            policy: Policy::Freeze,
+            growth: Growth::Lzmw,
+            ..Options::default()
+        };
+        let mut trie = Trie::new();
+        let mut codes = Codes::new(opts);
+        let mut frozen = None;
+        for word in sample(0, 50_000).chunks(5) {
+            trie.grow(opts.growth, word[0] as u32, &word[1..], &mut codes);
+            match frozen {
+                None if codes.full() => frozen = Some(trie.nodes.len()),
+                None => {}
+                Some(nodes) => assert_eq!(trie.nodes.len(), nodes),
+            }
+        }
+        assert!(frozen.is_some(), "словник не заповнився");
+    }
+
+    // Пошкоджений потік має дати Err, а не паніку чи нескінченний цикл.
+    #[test]
+    fn corrupt_streams() {
+        let data = sample(600, 200);
+        let mut streams = Vec::new();
+        for growth in [Growth::Lzw, Growth::Lzmw, Growth::Lzap] {
+            for (min_bits, max_bits, policy) in [(9, 9, Policy::Reset), (9, 12, Policy::Ratio)] {
+                let opts = Options {
+                    min_bits,
+                    max_bits,
+                    policy,
+                    growth,
+                };
+                streams.push(encode_bytes(&data, opts).unwrap());
+            }
+        }
+
+        let mut x = 987654321u32;
+        let mut next = move || {
+            x = x.wrapping_mul(1103515245).wrapping_add(12345);
+            x >> 8
+        };
+        for packed in &streams {
+            // без END_CODE потік обірваний
+            for n in 0..packed.len() {
+                assert!(decode_bytes(&packed[..n]).is_err(), "обрізано до {n}");
+            }
+            // інвертований біт може дати інші, але коректні коди: досить без паніки
+            for _ in 0..500 {
+                let mut bad = packed.clone();
+                let bit = next() as usize % (bad.len() * 8);
+                bad[bit / 8] ^= 1 << (bit % 8);
+                let _ = decode_bytes(&bad);
+            }
+            // правильний заголовок і випадкові коди
+            for _ in 0..200 {
+                // заголовок версії 4: 3 + 5 + 4 байти
+                let mut bad = packed[..12].to_vec();
+                let len = next() as usize % 2_000;
+                bad.extend((0..len).map(|_| next() as u8));
+                let _ = decode_bytes(&bad);
+            }
+        }
+        // зовсім випадкові байти не проходять перевірку заголовка
+        for _ in 0..1_000 {
+            let len = next() as usize % 64;
+            let bad: Vec<u8> = (0..len).map(|_| next() as u8).collect();
+            assert!(decode_bytes(&bad).is_err());
+        }
+    }
+
+    // Фікстури з testdata/make_z.py (порт compress 4.0, перевірені gzip -d):
+    // декодер відтворює вхід, а кодер - ті самі байти, разом із CLEAR у -b9 і -b12.
+    #[test]
+    fn z_fixtures() {
+        let data = include_bytes!("../testdata/sample.bin");
+        let fixtures: [(usize, &[u8]); 3] = [
+            (9, include_bytes!("../testdata/sample.b9.Z")),
+            (12, include_bytes!("../testdata/sample.b12.Z")),
+            (16, include_bytes!("../testdata/sample.b16.Z")),
+        ];
+        for (bits, packed) in fixtures {
+            let mut unpacked = Vec::new();
+            decode_z_stream(packed, &mut unpacked).unwrap();
+            assert!(unpacked == data, "-b{bits}: декодування");
+            let mut repacked = Vec::new();
+            encode_z_stream(&data[..], &mut repacked, bits).unwrap();
+            assert!(repacked == packed, "-b{bits}: кодування");
+        }
+    }
+
+    // Таблиця з хешем слова замість ключа не змінює вихід кодера.
+    #[test]
+    fn matches_hashmap_encoder() {
+        let dir = std::env::temp_dir();
+        let id = std::process::id();
+        let input = dir.join(format!("lzw-test-{id}.hm.in"));
+        let packed = dir.join(format!("lzw-test-{id}.hm.lzw"));
+        let (input, packed) = (input.to_str().unwrap(), packed.to_str().unwrap());
+        std::fs::write(input, sample(30_000, 30_000)).unwrap();
+        for (max_bits, policy) in [(9, Policy::Reset), (12, Policy::Ratio), (16, Policy::Freeze)] {
+            let opts = Options {
+                max_bits,
+                policy,
+                ..Options::default()
+            };
+            encode_hashmap(input, packed, opts).unwrap();
+            let expected = std::fs::read(packed).unwrap();
+            encode_with(input, packed, opts).unwrap();
+            assert!(std::fs::read(packed).unwrap() == expected, "{opts:?}");
+        }
+        for path in [input, packed] {
+            std::fs::remove_file(path).unwrap();
+        }
+    }
+
+    fn decode_error(e: &Error) -> Option<&DecodeError> {
+        e.get_ref().and_then(|e| e.downcast_ref::<DecodeError>())
+    }
+
+    // Коди шириною як у декодері GIF (ширина росте, коли next_code досягає
+    // 2^bits; на 4096 словах слова більше не додаються), нарізані на підблоки.
+    fn gif_stream(min_code_size: u8, codes: &[u32]) -> Vec<u8> {
+        let n = min_code_size as usize;
+        let clear = 1u32 << n;
+        let mut ds = BitWriter::new(Vec::new());
+        let (mut next_code, mut bits, mut first) = (clear + 2, n + 1, true);
+        for &code in codes {
+            ds.write_bits(code as u64, bits).unwrap();
+            if code == clear {
+                (next_code, bits, first) = (clear + 2, n + 1, true);
+                continue;
+            }
+            if !first && next_code < 1 << GIF_MAX_BITS {
+                next_code += 1;
+                if next_code == 1 << bits && bits < GIF_MAX_BITS {
+                    bits += 1;
+                }
+            }
+            first = false;
+        }
+        let packed = ds.finish().unwrap();
+        let mut out = vec![min_code_size];
+        for block in packed.chunks(GIF_BLOCK) {
+            out.push(block.len() as u8);
+            out.extend_from_slice(block);
+        }
+        out.push(0);
+        out
+    }
+
+    #[test]
+    fn gif_streams() {
+        for n in 2..=8u8 {
+            let mask = ((1u16 << n) - 1) as u8;
+            // рівномірні індекси дають короткі слова: 40 000 байтів - це понад
+            // 4096 кодів, тож кодер щонайменше раз очищує словник
+            let long: Vec<u8> = sample(20_000, 40_000).iter().map(|&c| c & mask).collect();
+            for data in [&[][..], &[mask][..], &long[..1000], &long] {
+                let packed = encode_gif(data, n).unwrap();
+                assert!(decode_gif(&packed).unwrap() == data, "n = {n}, {} байт", data.len());
+
+                // підблоки по 255 байт, останній коротший, далі нульовий і кінець
+                let mut rest = &packed[1..];
+                while rest[0] != 0 {
+                    let len = rest[0] as usize;
+                    rest = &rest[1 + len..];
+                    assert!(len == GIF_BLOCK || rest[0] == 0, "n = {n}: неповний підблок {len}");
+                }
+                assert_eq!(rest, [0]);
+            }
+        }
+
+        // відкладене очищення: після 4096 слів кодер не шле CLEAR, а декодер
+        // читає далі 12-бітні коди, поки CLEAR не прийде
+        let literals: Vec<u32> = (0..6000).map(|i| i * 7 % 4).collect();
+        let mut codes = vec![4];
+        codes.extend(&literals);
+        codes.extend([4, 3, 2, 5]);
+        let mut expected: Vec<u8> = literals.iter().map(|&c| c as u8).collect();
+        expected.extend([3, 2]);
+        assert!(decode_gif(&gif_stream(2, &codes)).unwrap() == expected);
+        // останнє слово (код 4095) додає 4091-й код після CLEAR, і воно доступне
+        // вже в заповненому словнику
+        let mut codes = vec![4];
+        codes.extend(&literals[..4091]);
+        codes.extend([4095, 5]);
+        let unpacked = decode_gif(&gif_stream(2, &codes)).unwrap();
+        assert_eq!(unpacked[4091..], [literals[4089] as u8, literals[4090] as u8]);
+        // код, якого ще немає у словнику
+        let e = decode_gif(&gif_stream(2, &[4, 1, 2, 3, 12, 5])).unwrap_err();
+        assert_eq!(decode_error(&e), Some(&DecodeError::BadCode { code: 12, next: 8 }));
+
+        // обірвані підблоки і відсутній нульовий блок
+        let packed = encode_gif(&sample(0, 2000), 8).unwrap();
+        for cut in [1, 2, 100, 256, packed.len() - 1] {
+            let e = decode_gif(&packed[..cut]).unwrap_err();
+            assert_eq!(e.kind(), ErrorKind::UnexpectedEof, "обрізано до {cut}");
+            assert_eq!(decode_error(&e), Some(&DecodeError::Truncated));
+        }
+
+        // індекс, що не вміщується в n біт, і недопустимий розмір коду
+        assert_eq!(encode_gif(&[0, 4], 2).unwrap_err().kind(), ErrorKind::InvalidInput);
+        assert_eq!(encode_gif(&[200], 7).unwrap_err().kind(), ErrorKind::InvalidInput);
+        for n in [0, 1, 9] {
+            assert_eq!(encode_gif(b"", n).unwrap_err().kind(), ErrorKind::InvalidInput);
+        }
+        assert_eq!(decode_gif(&[9, 0]).unwrap_err().kind(), ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn dictionaries() {
+        let dir = std::env::temp_dir();
+        let id = std::process::id();
+        let paths: Vec<String> = ["a", "b", "other", "dict"]
+            .iter()
+            .map(|name| dir.join(format!("lzw-test-{id}.dict-{name}")).to_str().unwrap().to_string())
+            .collect();
+        let [a, b, other, saved] = [&paths[0], &paths[1], &paths[2], &paths[3]];
+        let text = sample(20_000, 0);
+        std::fs::write(a, &text[..10_000]).unwrap();
+        std::fs::write(b, &text[10_000..]).unwrap();
+        std::fs::write(other, sample(0, 5_000)).unwrap();
+        let dict = Dictionary::train(&[a, b], 300).unwrap();
+        let wrong = Dictionary::train(&[other], 300).unwrap();
+        assert!(dict.len() > 0 && dict.len() <= 300);
+        assert_ne!(dict.id(), wrong.id());
+
+        // 10-бітні коди з Reset: на 200 000 байтах словник очищається багато
+        // разів, і після кожного CLEAR_CODE навчені слова мають лишатися
+        let mut data = sample(20_000, 200_000);
+        data.extend(&text);
+        let opts = Options {
+            max_bits: 10,
+            policy: Policy::Reset,
+            ..Options::default()
+        };
+        let mut packed = Vec::new();
+        encode_stream_with(&data[..], &mut packed, opts, Some(&dict)).unwrap();
+        let mut unpacked = Vec::new();
+        decode_stream_with(&packed[..], &mut unpacked, Some(&dict)).unwrap();
+        assert!(unpacked == data);
+
+        // словник із тих самих даних стискає краще за порожній
+        let mut primed = Vec::new();
+        encode_stream_with(&text[..], &mut primed, Options::default(), Some(&dict)).unwrap();
+        assert!(primed.len() < encode_bytes(&text, Options::default()).unwrap().len());
+
+        // без словника або з чужим: DecodeError::Dictionary
+        let e = decode_bytes(&packed).unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::InvalidData);
+        assert_eq!(decode_error(&e), Some(&DecodeError::Dictionary { id: dict.id(), given: 0 }));
+        let e = decode_stream_with(&packed[..], &mut Vec::new(), Some(&wrong)).unwrap_err();
+        let given = wrong.id();
+        assert_eq!(decode_error(&e), Some(&DecodeError::Dictionary { id: dict.id(), given }));
+        // потік без словника декодується, хоч би який словник передано
+        let plain = encode_bytes(&text, opts).unwrap();
+        let mut unpacked = Vec::new();
+        decode_stream_with(&plain[..], &mut unpacked, Some(&wrong)).unwrap();
+        assert!(unpacked == text);
+
+        dict.save(saved).unwrap();
+        assert_eq!(Dictionary::load(saved).unwrap(), dict);
+        let bytes = std::fs::read(saved).unwrap();
+        // змінений ID і змінене слово не збігаються з хешем вмісту
+        for pos in [4, bytes.len() - 1] {
+            let mut bad = bytes.clone();
+            bad[pos] ^= 1;
+            std::fs::write(saved, &bad).unwrap();
+            let e = Dictionary::load(saved).unwrap_err();
+            assert_eq!(e.kind(), ErrorKind::InvalidData, "байт {pos}");
+        }
+        for path in &paths {
+            std::fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn files() {
+        let dir = std::env::temp_dir();
+        let id = std::process::id();
+        let input = dir.join(format!("lzw-test-{id}.in"));
+        let packed = dir.join(format!("lzw-test-{id}.lzw"));
+        let output = dir.join(format!("lzw-test-{id}.out"));
+        let (input, packed, output) = (
+            input.to_str().unwrap(),
+            packed.to_str().unwrap(),
+            output.to_str().unwrap(),
+        );
+        let data = sample(30_000, 30_000);
+        std::fs::write(input, &data).unwrap();
+        encode(input, packed).unwrap();
+        decode(packed, output).unwrap();
+        assert!(std::fs::read(output).unwrap() == data);
+        for path in [input, packed, output] {
+            std::fs::remove_file(path).unwrap();
+        }
+    }
 }
@@ -1,142 +1,726 @@
-// mod bwt;
-// mod mtf;
-// mod utils;
 mod bwt;
 mod huffman;
+mod lz78;
+mod lzss;
 mod lzw;
 mod mtf;
 mod utils;
 
-use std::io::Result;
-// use mtf::LinkedList;
-
-fn fun_mtf(types: &str, num: &str) -> Result<()> {
-    let test_path = "test_files/".to_string() + types + "/test" + num + "." + types;
-    let test_path_out = "test_files_mtf/".to_string() + types + "/test" + num + ".mtf";
-    let test_path_decode = "test_files/".to_string() + types + "/test" + num + ".decmtf";
-    // mtf::encode(&test_path, &test_path_out)?;
-    // println!("Encoded");
-    mtf::decode(&test_path_out, &test_path_decode)?;
-    println!("Decoded");
+use std::collections::hash_map::RandomState;
+use std::env;
+use std::fs::{self, File, OpenOptions};
+use std::hash::{BuildHasher, Hasher};
+use std::io::{self, ErrorKind, Read, Result, Write};
+#[cfg(unix)]
+use std::os::unix::fs::OpenOptionsExt;
+use std::process::ExitCode;
+use std::str::FromStr;
+use std::time::Instant;
+
+const USAGE: &str = "\
+usage: LAB5-BWT-MTF [-v] <command> [options] [input [output]]
+
+Input and output default to '-' (stdin/stdout). Nothing is printed on
+success unless -v is given.
+
+commands:
+  compress   [-a lzw|lz78|lzss|z|huffman] [--dict FILE]
+  decompress [--dict FILE]              the format is taken from the header
+  bwt        [-d] [--sa]
+  mtf        [-d] [--block N]
+  huffman    [-d] [--max-len N | --adaptive | --order1 | --blocks | --chunk N]
+  lzw        [-d] [--min-bits N] [--max-bits N] [--policy reset|freeze|ratio]
+             [--growth lzw|lzmw|lzap] [--dict FILE] [--z]
+  lzw train  -o DICT [--words N] SAMPLE...
+  bench      [-a CODEC,...] [-t TYPE]... [FILE...]
+  bench      --lzw-encoder [--rounds N] [FILE...]
+  mtf-stats  [--block N] [--reset] [--json] [input]
+  report     REPORT [-t TYPE]...         tables over test_files*/ in the
+             current directory; REPORT is one of lzw-sizes, lzw-growth,
+             lzw-dict, huffman-sizes, huffman-analysis, huffman-decode,
+             huffman-order1
+  help";
+
+const CODECS: [&str; 5] = ["lzw", "lz78", "lzss", "z", "huffman"];
+// типи файлів у наборах test_files*/<тип>/test1..10.<тип>
+const FILE_TYPES: [&str; 5] = ["pdf", "mov", "3mf", "exe", "csv"];
+
+// Невдача команди: помилка у використанні (код 2) або під час роботи (код 1).
+enum Failure {
+    Usage(String),
+    Io(io::Error),
+}
+
+impl From<io::Error> for Failure {
+    fn from(e: io::Error) -> Self {
+        Failure::Io(e)
+    }
+}
+
+type CliResult<T = ()> = std::result::Result<T, Failure>;
+
+fn usage(msg: impl Into<String>) -> Failure {
+    Failure::Usage(msg.into())
+}
+
+fn unknown(opt: &str) -> Failure {
+    usage(format!("unknown option '{opt}'"))
+}
+
+// Аргументи підкоманди: опції йдуть упереміш з позиційними, "-" - позиційний
+// (stdin/stdout), після "--" все позиційне.
+struct Args {
+    args: std::vec::IntoIter<String>,
+    paths: Vec<String>,
+    only_paths: bool,
+    verbose: bool,
+}
+
+impl Args {
+    fn new(args: Vec<String>, verbose: bool) -> Self {
+        Self {
+            args: args.into_iter(),
+            paths: Vec::new(),
+            only_paths: false,
+            verbose,
+        }
+    }
+
+    // Наступна опція; позиційні аргументи відкладаються в paths.
+    fn next_opt(&mut self) -> Option<String> {
+        for arg in self.args.by_ref() {
+            if self.only_paths || arg == "-" || !arg.starts_with('-') {
+                self.paths.push(arg);
+            } else if arg == "--" {
+                self.only_paths = true;
+            } else if arg == "-v" || arg == "--verbose" {
+                self.verbose = true;
+            } else {
+                return Some(arg);
+            }
+        }
+        None
+    }
+
+    fn value<T: FromStr>(&mut self, opt: &str) -> CliResult<T> {
+        let arg = self
+            .args
+            .next()
+            .ok_or_else(|| usage(format!("{opt}: missing value")))?;
+        arg.parse()
+            .map_err(|_| usage(format!("{opt}: invalid value '{arg}'")))
+    }
+
+    // Вхід і вихід: до двох позиційних, за замовчуванням "-".
+    fn io(&mut self) -> CliResult<(String, String)> {
+        if let Some(extra) = self.paths.get(2) {
+            return Err(usage(format!("unexpected argument '{extra}'")));
+        }
+        let mut paths = std::mem::take(&mut self.paths).into_iter();
+        let input = paths.next().unwrap_or_else(|| "-".to_string());
+        let output = paths.next().unwrap_or_else(|| "-".to_string());
+        Ok((input, output))
+    }
+}
+
+// Тимчасовий файл, що видаляється разом зі значенням. Створюється одразу з
+// випадковим іменем і create_new: у спільному каталозі чужий файл чи
+// символьне посилання на його місці не підхопиться, а кодеки потім
+// відкривають за шляхом уже наш файл.
+struct TempFile(String);
+
+impl TempFile {
+    fn new(tag: &str) -> Result<Self> {
+        loop {
+            let suffix = RandomState::new().build_hasher().finish();
+            let path = env::temp_dir().join(format!("lab5-{}-{suffix:016x}-{tag}", std::process::id()));
+            let Some(name) = path.to_str() else {
+                return Err(io::Error::new(
+                    ErrorKind::InvalidInput,
+                    "temporary directory path is not valid UTF-8",
+                ));
+            };
+            let mut options = OpenOptions::new();
+            options.write(true).create_new(true);
+            #[cfg(unix)]
+            options.mode(0o600);
+            match options.open(&path) {
+                Ok(_) => return Ok(Self(name.to_string())),
+                Err(e) if e.kind() == ErrorKind::AlreadyExists => continue,
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.0);
+    }
+}
+
+// Кодеки працюють зі шляхами, тому "-" підміняється тимчасовим файлом: stdin
+// копіюється в нього перед запуском, а результат - у stdout після.
+fn transform(
+    input: &str,
+    output: &str,
+    verbose: bool,
+    f: impl FnOnce(&str, &str) -> Result<()>,
+) -> Result<()> {
+    let start = Instant::now();
+    let tmp_in = match input {
+        "-" => {
+            let tmp = TempFile::new("in")?;
+            io::copy(&mut io::stdin().lock(), &mut File::create(&tmp.0)?)?;
+            Some(tmp)
+        }
+        _ => {
+            // щоб помилка називала файл, а не лише "No such file"
+            File::open(input).map_err(|e| io::Error::new(e.kind(), format!("{input}: {e}")))?;
+            None
+        }
+    };
+    let tmp_out = match output {
+        "-" => Some(TempFile::new("out")?),
+        _ => None,
+    };
+    let file_read = tmp_in.as_ref().map_or(input, |tmp| tmp.0.as_str());
+    let file_write = tmp_out.as_ref().map_or(output, |tmp| tmp.0.as_str());
+
+    f(file_read, file_write)?;
+
+    let in_size = fs::metadata(file_read)?.len();
+    let out_size = fs::metadata(file_write)?.len();
+    if tmp_out.is_some() {
+        let mut stdout = io::stdout().lock();
+        io::copy(&mut File::open(file_write)?, &mut stdout)?;
+        stdout.flush()?;
+    }
+    if verbose {
+        eprintln!(
+            "{input} -> {output}: {in_size} -> {out_size} bytes ({:.3}), {:.3?}",
+            in_size as f64 / out_size.max(1) as f64,
+            start.elapsed()
+        );
+    }
     Ok(())
 }
 
-fn fun_bwt(types: &str, num: &str) -> Result<()> {
-    let test_path = "test_files/".to_string() + types + "/test" + num + "." + types;
-    let test_path_out = "test_files_bwt/".to_string() + types + "/test" + num + ".bwt";
-    let test_path_decode = "test_files/".to_string() + types + "/test" + num + ".decbwt";
-    bwt::encode(&test_path, &test_path_out)?;
-    println!("Encoded");
-    bwt::decode(&test_path_out, &test_path_decode)?;
-    // println!("Decoded");
+fn compress_file(
+    codec: &str,
+    file_read: &str,
+    file_write: &str,
+    dict: Option<&lzw::Dictionary>,
+) -> Result<()> {
+    match (codec, dict) {
+        ("lzw", Some(dict)) => {
+            lzw::encode_with_dict(file_read, file_write, lzw::Options::default(), dict)
+        }
+        ("lzw", None) => lzw::encode(file_read, file_write),
+        ("lz78", _) => lz78::encode(file_read, file_write),
+        ("lzss", _) => lzss::encode(file_read, file_write, lzss::Params::default()),
+        ("z", _) => lzw::encode_z(file_read, file_write, lzw::Z_MAX_BITS),
+        ("huffman", _) => huffman::encode(file_read, file_write),
+        _ => unreachable!("codec is checked while parsing"),
+    }
+}
+
+// Формат визначається за магією на початку файла.
+fn decompress_file(
+    file_read: &str,
+    file_write: &str,
+    dict: Option<&lzw::Dictionary>,
+) -> Result<()> {
+    let mut magic = Vec::new();
+    File::open(file_read)?.take(3).read_to_end(&mut magic)?;
+    match (magic.as_slice(), dict) {
+        (b"LZW", Some(dict)) => lzw::decode_with_dict(file_read, file_write, dict),
+        (b"LZW", None) => lzw::decode(file_read, file_write),
+        (b"L78", _) => lz78::decode(file_read, file_write),
+        (b"LZS", _) => lzss::decode(file_read, file_write),
+        (b"HUF", _) => huffman::decode(file_read, file_write),
+        ([0x1f, 0x9d, _], _) => lzw::decode_z(file_read, file_write),
+        _ => Err(io::Error::new(
+            ErrorKind::InvalidData,
+            "unknown compressed format",
+        )),
+    }
+}
+
+fn load_dict(path: Option<String>) -> Result<Option<lzw::Dictionary>> {
+    path.map(|path| lzw::Dictionary::load(&path)).transpose()
+}
+
+fn compress(args: Vec<String>, verbose: bool) -> CliResult {
+    let mut args = Args::new(args, verbose);
+    let mut codec = "lzw".to_string();
+    let mut dict = None;
+    while let Some(opt) = args.next_opt() {
+        match opt.as_str() {
+            "-a" | "--algo" => codec = args.value(&opt)?,
+            "--dict" => dict = Some(args.value(&opt)?),
+            _ => return Err(unknown(&opt)),
+        }
+    }
+    if !CODECS.contains(&codec.as_str()) {
+        return Err(usage(format!("unknown codec '{codec}'")));
+    }
+    if dict.is_some() && codec != "lzw" {
+        return Err(usage("--dict works only with -a lzw"));
+    }
+    let (input, output) = args.io()?;
+    let dict = load_dict(dict)?;
+    transform(&input, &output, args.verbose, |r, w| {
+        compress_file(&codec, r, w, dict.as_ref())
+    })?;
+    Ok(())
+}
+
+fn decompress(args: Vec<String>, verbose: bool) -> CliResult {
+    let mut args = Args::new(args, verbose);
+    let mut dict = None;
+    while let Some(opt) = args.next_opt() {
+        match opt.as_str() {
+            "--dict" => dict = Some(args.value(&opt)?),
+            _ => return Err(unknown(&opt)),
+        }
+    }
+    let (input, output) = args.io()?;
+    let dict = load_dict(dict)?;
+    transform(&input, &output, args.verbose, |r, w| {
+        decompress_file(r, w, dict.as_ref())
+    })?;
+    Ok(())
+}
+
+fn bwt_cmd(args: Vec<String>, verbose: bool) -> CliResult {
+    let mut args = Args::new(args, verbose);
+    let mut decode = false;
+    let mut sa = false;
+    while let Some(opt) = args.next_opt() {
+        match opt.as_str() {
+            "-d" | "--decode" => decode = true,
+            "--sa" => sa = true,
+            _ => return Err(unknown(&opt)),
+        }
+    }
+    let (input, output) = args.io()?;
+    transform(&input, &output, args.verbose, |r, w| match (decode, sa) {
+        (true, _) => bwt::decode(r, w),
+        (false, true) => bwt::encode_SA(r, w),
+        (false, false) => bwt::encode(r, w),
+    })?;
+    Ok(())
+}
+
+// Блочний формат MTF починається з тегу, звичайний заголовка не має.
+fn mtf_cmd(args: Vec<String>, verbose: bool) -> CliResult {
+    let mut args = Args::new(args, verbose);
+    let mut decode = false;
+    let mut block = None;
+    while let Some(opt) = args.next_opt() {
+        match opt.as_str() {
+            "-d" | "--decode" => decode = true,
+            "--block" => {
+                let n = args.value(&opt)?;
+                if n == 0 {
+                    return Err(usage("--block: must be positive"));
+                }
+                block = Some(n);
+            }
+            _ => return Err(unknown(&opt)),
+        }
+    }
+    // у звичайного потоку немає заголовка, тож блоки при декодуванні треба
+    // вказати явно; розмір має збігатися із заголовком блоків
+    let (input, output) = args.io()?;
+    transform(&input, &output, args.verbose, |r, w| {
+        match (decode, block) {
+            (true, Some(block)) => mtf::decode_blocks(r, w, block),
+            (true, None) => mtf::decode(r, w),
+            (false, Some(block)) => mtf::encode_blocks(r, w, block),
+            (false, None) => mtf::encode(r, w),
+        }
+    })?;
     Ok(())
 }
 
-fn fun_bwt_sa(types: &str, num: &str) -> Result<()> {
-    let test_path = "test_files/".to_string() + types + "/test" + num + "." + types;
-    let test_path_out = "test_files_bwtsa/".to_string() + types + "/test" + num + ".bwtsa";
-    let test_path_decode = "test_files/".to_string() + types + "/test" + num + ".decbwtsa";
-    bwt::encode_SA(&test_path, &test_path_out)?;
-    println!("Encoded");
-    bwt::decode(&test_path_out, &test_path_decode)?;
-    // println!("Decoded");
+#[derive(Clone, Copy)]
+enum HufMode {
+    Static(usize),
+    Adaptive,
+    Order1,
+    Blocks,
+    Chunked(usize),
+}
+
+fn huffman_cmd(args: Vec<String>, verbose: bool) -> CliResult {
+    let mut args = Args::new(args, verbose);
+    let mut decode = false;
+    let mut mode = None;
+    while let Some(opt) = args.next_opt() {
+        let next = match opt.as_str() {
+            "-d" | "--decode" => {
+                decode = true;
+                continue;
+            }
+            "--max-len" => HufMode::Static(args.value(&opt)?),
+            "--adaptive" => HufMode::Adaptive,
+            "--order1" => HufMode::Order1,
+            "--blocks" => HufMode::Blocks,
+            "--chunk" => HufMode::Chunked(args.value(&opt)?),
+            _ => return Err(unknown(&opt)),
+        };
+        if mode.replace(next).is_some() {
+            return Err(usage("huffman: choose only one encoding mode"));
+        }
+    }
+    let (input, output) = args.io()?;
+    let mode = mode.unwrap_or(HufMode::Static(huffman::MAX_CODE_LEN));
+    // декодер сам розпізнає режим за заголовком
+    transform(&input, &output, args.verbose, |r, w| match (decode, mode) {
+        (true, _) => huffman::decode(r, w),
+        (false, HufMode::Static(max_len)) => huffman::encode_limited(r, w, max_len),
+        (false, HufMode::Adaptive) => huffman::encode_adaptive(r, w),
+        (false, HufMode::Order1) => huffman::encode_order1(r, w),
+        (false, HufMode::Blocks) => huffman::encode_blocks(r, w),
+        (false, HufMode::Chunked(chunk)) => huffman::encode_chunked(r, w, chunk),
+    })?;
     Ok(())
 }
 
-fn fun_bwtmtf(types: &str, num: &str) -> Result<()> {
-    let test_path = "test_files_bwt/".to_string() + types + "/test" + num + ".bwt";
-    let test_path_out = "test_files_bwtmtf/".to_string() + types + "/test" + num + ".bwtmtf";
-    let test_path_decode = "test_files_csv/".to_string() + types + "/test" + num + ".dbw";
-    mtf::encode(&test_path, &test_path_out)?;
-    println!("Encoded");
-    // decode(&test_path_out, &test_path_decode)?;
-    // println!("Decoded");
+fn lzw_cmd(mut args: Vec<String>, verbose: bool) -> CliResult {
+    if args.first().map(String::as_str) == Some("train") {
+        args.remove(0);
+        return lzw_train(args, verbose);
+    }
+
+    let mut args = Args::new(args, verbose);
+    let mut decode = false;
+    let mut z = false;
+    let mut opts = lzw::Options::default();
+    let mut max_bits = None;
+    let mut dict = None;
+    while let Some(opt) = args.next_opt() {
+        match opt.as_str() {
+            "-d" | "--decode" => decode = true,
+            "--z" => z = true,
+            "--min-bits" => opts.min_bits = args.value(&opt)?,
+            "--max-bits" => max_bits = Some(args.value(&opt)?),
+            "--policy" => {
+                opts.policy = match args.value::<String>(&opt)?.as_str() {
+                    "reset" => lzw::Policy::Reset,
+                    "freeze" => lzw::Policy::Freeze,
+                    "ratio" => lzw::Policy::Ratio,
+                    other => return Err(usage(format!("{opt}: invalid value '{other}'"))),
+                }
+            }
+            "--growth" => {
+                opts.growth = match args.value::<String>(&opt)?.as_str() {
+                    "lzw" => lzw::Growth::Lzw,
+                    "lzmw" => lzw::Growth::Lzmw,
+                    "lzap" => lzw::Growth::Lzap,
+                    other => return Err(usage(format!("{opt}: invalid value '{other}'"))),
+                }
+            }
+            "--dict" => dict = Some(args.value(&opt)?),
+            _ => return Err(unknown(&opt)),
+        }
+    }
+    if z && dict.is_some() {
+        return Err(usage("--dict cannot be used with --z"));
+    }
+    // у .Z своя ширина за замовчуванням, як у compress
+    let z_bits = max_bits.unwrap_or(lzw::Z_MAX_BITS);
+    opts.max_bits = max_bits.unwrap_or(opts.max_bits);
+    let (input, output) = args.io()?;
+    let dict = load_dict(dict)?;
+    transform(&input, &output, args.verbose, |r, w| {
+        match (decode, z, dict.as_ref()) {
+            (true, true, _) => lzw::decode_z(r, w),
+            (true, false, Some(dict)) => lzw::decode_with_dict(r, w, dict),
+            (true, false, None) => lzw::decode(r, w),
+            (false, true, _) => lzw::encode_z(r, w, z_bits),
+            (false, false, Some(dict)) => lzw::encode_with_dict(r, w, opts, dict),
+            (false, false, None) => lzw::encode_with(r, w, opts),
+        }
+    })?;
     Ok(())
 }
 
-fn test_bwt(types: &str) -> Result<()> {
-    println!("type of file: {}", types);
-    fun_bwt(types, "1")?;
-    fun_bwt(types, "2")?;
-    fun_bwt(types, "3")?;
-    fun_bwt(types, "4")?;
-    fun_bwt(types, "5")?;
-    fun_bwt(types, "6")?;
-    fun_bwt(types, "7")?;
-    fun_bwt(types, "8")?;
-    fun_bwt(types, "9")?;
-    fun_bwt(types, "10")?;
+fn lzw_train(args: Vec<String>, verbose: bool) -> CliResult {
+    let mut args = Args::new(args, verbose);
+    let mut out = None;
+    let mut words = 1 << 16;
+    while let Some(opt) = args.next_opt() {
+        match opt.as_str() {
+            "-o" | "--output" => out = Some(args.value::<String>(&opt)?),
+            "--words" => words = args.value(&opt)?,
+            _ => return Err(unknown(&opt)),
+        }
+    }
+    let out = out.ok_or_else(|| usage("lzw train: missing -o DICT"))?;
+    if args.paths.is_empty() || args.paths.iter().any(|p| p == "-") {
+        return Err(usage(
+            "lzw train: expected sample files (stdin is not supported)",
+        ));
+    }
+    let samples: Vec<&str> = args.paths.iter().map(String::as_str).collect();
+    let dict = lzw::Dictionary::train(&samples, words)?;
+    dict.save(&out)?;
+    if args.verbose {
+        eprintln!("{out}: dictionary {:08x}, {} words", dict.id(), dict.len());
+    }
+    Ok(())
+}
+
+// Коефіцієнт стиснення і час кожного кодека на кожному файлі; розпакований
+// файл звіряється з початковим. З --lzw-encoder - швидкість кодера LZW проти
+// попереднього, на файлах або на відтворюваному наборі з lzw::bench_corpus.
+fn bench(args: Vec<String>, verbose: bool) -> CliResult {
+    let mut args = Args::new(args, verbose);
+    let mut codecs: Vec<String> = CODECS.iter().map(|c| c.to_string()).collect();
+    let mut files = Vec::new();
+    let mut lzw_encoder = false;
+    let mut rounds = 5;
+    while let Some(opt) = args.next_opt() {
+        match opt.as_str() {
+            "--lzw-encoder" => lzw_encoder = true,
+            "--rounds" => {
+                rounds = args.value(&opt)?;
+                if rounds == 0 {
+                    return Err(usage("--rounds: must be positive"));
+                }
+            }
+            "-a" | "--algo" => {
+                codecs = args
+                    .value::<String>(&opt)?
+                    .split(',')
+                    .map(str::to_string)
+                    .collect();
+            }
+            // набір test_files/<type>/test1..10.<type>
+            "-t" | "--type" => {
+                let types: String = args.value(&opt)?;
+                files.extend((1..=10).map(|num| format!("test_files/{types}/test{num}.{types}")));
+            }
+            _ => return Err(unknown(&opt)),
+        }
+    }
+    if let Some(codec) = codecs.iter().find(|c| !CODECS.contains(&c.as_str())) {
+        return Err(usage(format!("unknown codec '{codec}'")));
+    }
+    if args.paths.iter().any(|p| p == "-") {
+        return Err(usage("bench: stdin is not supported"));
+    }
+    files.append(&mut args.paths);
+    if lzw_encoder {
+        return bench_lzw_encoder(&files, rounds);
+    }
+    if files.is_empty() {
+        return Err(usage("bench: no input files"));
+    }
+
+    let packed = TempFile::new("bench.pack")?;
+    let unpacked = TempFile::new("bench.unpack")?;
+    let mut out = io::stdout().lock();
+    writeln!(out, "file\tcodec\tsize\tpacked\tratio\tencode\tdecode")?;
+    for file in &files {
+        let original = fs::read(file)?;
+        for codec in &codecs {
+            let start = Instant::now();
+            compress_file(codec, file, &packed.0, None)?;
+            let encode = start.elapsed();
+            let start = Instant::now();
+            decompress_file(&packed.0, &unpacked.0, None)?;
+            let decode = start.elapsed();
+
+            if fs::read(&unpacked.0)? != original {
+                let msg = format!("{codec} did not round-trip {file}");
+                return Err(io::Error::new(ErrorKind::InvalidData, msg).into());
+            }
+            let size = fs::metadata(&packed.0)?.len();
+            writeln!(
+                out,
+                "{file}\t{codec}\t{}\t{size}\t{:.3}\t{encode:.3?}\t{decode:.3?}",
+                original.len(),
+                original.len() as f64 / size.max(1) as f64,
+            )?;
+        }
+    }
     Ok(())
 }
 
-fn test_bwt_sa(types: &str) -> Result<()> {
-    println!("type of file: {}", types);
-    // fun_bwt_sa(types, "1")?;
-    // fun_bwt_sa(types, "2")?;
-    // fun_bwt_sa(types, "3")?;
-    // fun_bwt_sa(types, "4")?;
-    // fun_bwt_sa(types, "5")?;
-    // fun_bwt_sa(types, "6")?;
-    // fun_bwt_sa(types, "7")?;
-    // fun_bwt_sa(types, "8")?;
-    // fun_bwt_sa(types, "9")?;
-    fun_bwt_sa(types, "10")?;
+fn bench_lzw_encoder(files: &[String], rounds: usize) -> CliResult {
+    let packed = TempFile::new("bench.lzw")?;
+    let mut out = io::stdout().lock();
+    writeln!(out, "file\tmax_bits\thashmap\ttable\tspeedup")?;
+    for file in files {
+        lzw::bench_encoder(file, file, &packed.0, rounds, &mut out)?;
+    }
+    if files.is_empty() {
+        for (name, data) in lzw::bench_corpus(10 << 20) {
+            let input = TempFile::new(&format!("bench.{name}"))?;
+            fs::write(&input.0, data)?;
+            lzw::bench_encoder(name, &input.0, &packed.0, rounds, &mut out)?;
+        }
+    }
     Ok(())
 }
 
-fn test_mtf(types: &str) -> Result<()> {
-    println!("type of file: {}", types);
-    fun_mtf(types, "1")?;
-    fun_mtf(types, "2")?;
-    fun_mtf(types, "3")?;
-    fun_mtf(types, "4")?;
-    fun_mtf(types, "5")?;
-    fun_mtf(types, "6")?;
-    fun_mtf(types, "7")?;
-    fun_mtf(types, "8")?;
-    fun_mtf(types, "9")?;
-    fun_mtf(types, "10")?;
+// Набори вхідних файлів звіту: каталог, розширення (порожнє - сам тип файлу)
+// і перетворення, яке пройшли файли.
+const REPORT_SETS: [(&str, &str, &str); 4] = [
+    ("test_files", "", ""),
+    ("test_files_bwt", "bwt", "bwt+"),
+    ("test_files_mtf", "mtf", "mtf+"),
+    ("test_files_bwtmtf", "bwtmtf", "bwt+mtf+"),
+];
+
+// Коефіцієнт - розмір вихідного test_files/<тип>/testN відносно стиснутого
+// файлу набору; стискається тут же, у тимчасовий файл. Кожен кодек дає в
+// рядку три стовпці: середній, мінімальний і максимальний коефіцієнт.
+fn report_ratios(codecs: &[&str], types: &[&str]) -> Result<()> {
+    let packed = TempFile::new("report")?;
+    let header: String = codecs
+        .iter()
+        .map(|c| format!("[*{c} середній*][*Мінімальний*][*Максимальний*]"))
+        .collect();
+    for (dir, ext, caption) in REPORT_SETS {
+        println!(
+            "#figure(\n table(\n columns: {},\n table.header[*Тип файлу*]{header}, ",
+            1 + 3 * codecs.len()
+        );
+        for t in types {
+            let ext = if ext.is_empty() { t } else { ext };
+            print!("[{t}]");
+            for codec in codecs {
+                let (mut sum, mut min, mut max) = (0.0, f64::MAX, 0.0f64);
+                for num in 1..=10 {
+                    let size = fs::metadata(format!("test_files/{t}/test{num}.{t}"))?.len();
+                    compress_file(codec, &format!("{dir}/{t}/test{num}.{ext}"), &packed.0, None)?;
+                    let ratio = size as f64 / fs::metadata(&packed.0)?.len() as f64;
+                    sum += ratio;
+                    min = min.min(ratio);
+                    max = max.max(ratio);
+                }
+                print!(", [{:.3}], [{min:.3}], [{max:.3}]", sum / 10.);
+            }
+            println!(",");
+        }
+        println!("),\n caption: \"{caption}{}\",\n)\n", codecs.join(", "));
+    }
     Ok(())
 }
 
-fn test_bwtmtf(types: &str) -> Result<()> {
-    println!("type of file: {}", types);
-    fun_bwtmtf(types, "1")?;
-    fun_bwtmtf(types, "2")?;
-    fun_bwtmtf(types, "3")?;
-    fun_bwtmtf(types, "4")?;
-    fun_bwtmtf(types, "5")?;
-    fun_bwtmtf(types, "6")?;
-    fun_bwtmtf(types, "7")?;
-    fun_bwtmtf(types, "8")?;
-    fun_bwtmtf(types, "9")?;
-    fun_bwtmtf(types, "10")?;
+// Таблиці для звіту лабораторної з наборів test_files*/<тип>/test1..10;
+// без -t - по всіх типах.
+fn report(args: Vec<String>, verbose: bool) -> CliResult {
+    let mut args = Args::new(args, verbose);
+    let mut types = Vec::new();
+    while let Some(opt) = args.next_opt() {
+        match opt.as_str() {
+            "-t" | "--type" => types.push(args.value::<String>(&opt)?),
+            _ => return Err(unknown(&opt)),
+        }
+    }
+    let mut types: Vec<&str> = types.iter().map(String::as_str).collect();
+    if types.is_empty() {
+        types = FILE_TYPES.to_vec();
+    }
+    let [name] = args.paths.as_slice() else {
+        return Err(usage("report: expected exactly one REPORT"));
+    };
+
+    match name.as_str() {
+        // LZ78 - базовий рівень для порівняння з LZW
+        "lzw-sizes" => report_ratios(&["lzw", "lz78"], &types)?,
+        "lzw-growth" => types.iter().try_for_each(|t| lzw::bench_growth(t))?,
+        "lzw-dict" => types.iter().try_for_each(|t| lzw::bench_dict(t))?,
+        "huffman-sizes" => huffman::report_sizes(&types)?,
+        "huffman-analysis" => huffman::report_analysis(&types)?,
+        "huffman-decode" => types.iter().try_for_each(|t| huffman::bench_decode(t))?,
+        "huffman-order1" => types.iter().try_for_each(|t| huffman::order1_gain(t))?,
+        other => return Err(usage(format!("unknown report '{other}'"))),
+    }
     Ok(())
 }
 
-fn main() -> Result<()> {
-    println!("Main");
-    // test_bwt_sa("csv")?;
-
-    // println!("BWT:");
-    // test_bwt("pdf")?;
-    // test_bwt("mov")?;
-    // test_bwt("3mf")?;
-    // test_bwt("exe")?;
-    // test_bwt("csv")?;
-
-    println!("MTF:");
-    // test_mtf("pdf")?;
-    // test_mtf("mov")?;
-    // test_mtf("3mf")?;
-    // test_mtf("exe")?;
-    test_mtf("csv")?;
-
-    // println!("BWTMTF:");
-    // test_bwtmtf("pdf")?;
-    // test_bwtmtf("mov")?;
-    // test_bwtmtf("3mf")?;
-    // test_bwtmtf("exe")?;
-    // test_bwtmtf("csv")?;
+fn mtf_stats(args: Vec<String>, verbose: bool) -> CliResult {
+    let mut args = Args::new(args, verbose);
+    let mut block = mtf::BWT_BLOCK;
+    let mut reset = false;
+    let mut json = false;
+    while let Some(opt) = args.next_opt() {
+        match opt.as_str() {
+            "--block" => {
+                block = args.value(&opt)?;
+                if block == 0 {
+                    return Err(usage("--block: must be positive"));
+                }
+            }
+            "--reset" => reset = true,
+            "--json" => json = true,
+            _ => return Err(unknown(&opt)),
+        }
+    }
+    let stats = match args.paths.as_slice() {
+        [] => mtf::collect_stats(io::stdin().lock(), block, reset)?,
+        [file] if file == "-" => mtf::collect_stats(io::stdin().lock(), block, reset)?,
+        [file] => {
+            let reader = File::open(file).map_err(|e| io::Error::new(e.kind(), format!("{file}: {e}")))?;
+            mtf::collect_stats(reader, block, reset)?
+        }
+        [_, extra, ..] => return Err(usage(format!("unexpected argument '{extra}'"))),
+    };
+    let mut out = io::stdout().lock();
+    if json {
+        writeln!(out, "{}", stats.to_json())?;
+    } else {
+        write!(out, "{}", stats)?;
+    }
     Ok(())
 }
+
+fn main() -> ExitCode {
+    let mut args: Vec<String> = env::args().skip(1).collect();
+    // -v перед командою діє на неї так само, як і після
+    let global = args
+        .iter()
+        .take_while(|a| *a == "-v" || *a == "--verbose")
+        .count();
+    let verbose = global > 0;
+    let mut args = args.split_off(global);
+    let command = if args.is_empty() {
+        String::new()
+    } else {
+        args.remove(0)
+    };
+
+    let result = match command.as_str() {
+        "compress" => compress(args, verbose),
+        "decompress" => decompress(args, verbose),
+        "bwt" => bwt_cmd(args, verbose),
+        "mtf" => mtf_cmd(args, verbose),
+        "huffman" => huffman_cmd(args, verbose),
+        "lzw" => lzw_cmd(args, verbose),
+        "bench" => bench(args, verbose),
+        "mtf-stats" => mtf_stats(args, verbose),
+        "report" => report(args, verbose),
+        "help" | "-h" | "--help" => writeln!(io::stdout(), "{USAGE}").map_err(Failure::from),
+        "" => Err(usage("missing command")),
+        other => Err(usage(format!("unknown command '{other}'"))),
+    };
+
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(Failure::Usage(msg)) => {
+            eprintln!("error: {msg}\n\n{USAGE}");
+            ExitCode::from(2)
+        }
+        Err(Failure::Io(e)) => {
+            eprintln!("error: {e}");
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -1,33 +1,52 @@
 #![allow(dead_code)]
 use std::cell::RefCell;
-use std::fmt::Debug;
+use std::collections::BTreeMap;
+use std::fmt::{self, Debug, Display};
 use std::fs::File;
-use std::io::{BufReader, BufWriter, Read, Result, Write};
+use std::io::{BufReader, BufWriter, Error, ErrorKind, Read, Result, Write};
+use std::marker::PhantomData;
 use std::rc::{Rc, Weak};
 
+use rayon::prelude::*;
+
+use crate::bwt;
+
 type Link<T> = Option<Rc<RefCell<Node<T>>>>;
 type WeakLink<T> = Option<Weak<RefCell<Node<T>>>>;
 
-#[derive(Debug)]
 struct Node<T> {
     val: T,
     next: Link<T>,
     prev: WeakLink<T>,
 }
 
-#[derive(Debug)]
 pub struct LinkedList<T> {
     head: Link<T>,
     tail: Link<T>,
+    len: usize,
 }
 
-impl<T: PartialEq + Debug + Clone> LinkedList<T> {
+impl<T> LinkedList<T> {
     pub fn new() -> Self {
         Self {
             head: None,
             tail: None,
+            len: 0,
         }
     }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn clear(&mut self) {
+        *self = Self::new();
+    }
+
     pub fn push_front(&mut self, val: T) {
         let new_head = Rc::new(RefCell::new(Node {
             val,
@@ -47,106 +66,659 @@ impl<T: PartialEq + Debug + Clone> LinkedList<T> {
                 self.tail = Some(new_head);
             }
         }
+        self.len += 1;
     }
 
-    pub fn find_remove(&mut self, val: &T) -> Option<usize> {
-        let mut index = 0;
+    pub fn push_back(&mut self, val: T) {
+        let new_tail = Rc::new(RefCell::new(Node {
+            val,
+            next: None,
+            prev: None,
+        }));
+
+        match self.tail.take() {
+            Some(tail) => {
+                new_tail.borrow_mut().prev = Some(Rc::downgrade(&tail));
+                tail.borrow_mut().next = Some(new_tail.clone());
+                self.tail = Some(new_tail);
+            }
+
+            None => {
+                self.head = Some(new_tail.clone());
+                self.tail = Some(new_tail);
+            }
+        }
+        self.len += 1;
+    }
+
+    pub fn pop_front(&mut self) -> Option<T> {
+        let head = self.head.clone()?;
+        Some(self.unlink(head))
+    }
+
+    pub fn pop_back(&mut self) -> Option<T> {
+        let tail = self.tail.clone()?;
+        Some(self.unlink(tail))
+    }
+
+    // Вставляє елемент так, щоб він опинився на позиції index.
+    pub fn insert_at(&mut self, index: usize, val: T) {
+        assert!(
+            index <= self.len,
+            "LinkedList: індекс вставки {index} більший за довжину {}",
+            self.len
+        );
+        if index == 0 {
+            return self.push_front(val);
+        }
+        if index == self.len {
+            return self.push_back(val);
+        }
+
+        let next = self.node_at(index).unwrap();
+        let prev = next.borrow().prev.as_ref().and_then(|w| w.upgrade()).unwrap();
+        let new_node = Rc::new(RefCell::new(Node {
+            val,
+            next: Some(next.clone()),
+            prev: Some(Rc::downgrade(&prev)),
+        }));
+        next.borrow_mut().prev = Some(Rc::downgrade(&new_node));
+        prev.borrow_mut().next = Some(new_node);
+        self.len += 1;
+    }
+
+    pub fn index_remove(&mut self, index: usize) -> Option<T> {
+        let node = self.node_at(index)?;
+        Some(self.unlink(node))
+    }
+
+    fn node_at(&self, index: usize) -> Link<T> {
+        if index >= self.len {
+            return None;
+        }
+
         let mut current = self.head.clone();
-        while let Some(current_node) = current.as_ref() {
-            let next = current_node.borrow().next.clone();
-            if current_node.borrow().val == *val {
-                let mut current_borrow = current_node.borrow_mut();
-                let prev = current_borrow.prev.take();
-                let next = current_borrow.next.take();
-                match (prev.as_ref().and_then(|w| w.upgrade()), next.as_ref()) {
-                    (Some(prev_strong), Some(next_strong)) => {
-                        prev_strong.borrow_mut().next = Some(next_strong.clone());
-                        next_strong.borrow_mut().prev = prev; // 'prev_link' - це Option<Weak<...>>
-                    }
+        for _ in 0..index {
+            current = current.and_then(|node| node.borrow().next.clone());
+        }
+        current
+    }
 
-                    (None, Some(next_strong)) => {
-                        next_strong.borrow_mut().prev = None;
-                        self.head = Some(next_strong.clone());
-                    }
+    // Викидає вузол зі списку і повертає його значення.
+    fn unlink(&mut self, node: Rc<RefCell<Node<T>>>) -> T {
+        let mut node_borrow = node.borrow_mut();
+        let prev = node_borrow.prev.take();
+        let next = node_borrow.next.take();
+        match (prev.as_ref().and_then(|w| w.upgrade()), next.as_ref()) {
+            (Some(prev_strong), Some(next_strong)) => {
+                prev_strong.borrow_mut().next = Some(next_strong.clone());
+                next_strong.borrow_mut().prev = prev; // 'prev_link' - це Option<Weak<...>>
+            }
 
-                    (Some(prev_strong), None) => {
-                        prev_strong.borrow_mut().next = None;
-                        self.tail = Some(prev_strong.clone());
-                    }
+            (None, Some(next_strong)) => {
+                next_strong.borrow_mut().prev = None;
+                self.head = Some(next_strong.clone());
+            }
 
-                    (None, None) => {
-                        self.head = None;
-                        self.tail = None;
-                    }
-                }
-                drop(current_borrow);
+            (Some(prev_strong), None) => {
+                prev_strong.borrow_mut().next = None;
+                self.tail = Some(prev_strong.clone());
+            }
+
+            (None, None) => {
+                self.head = None;
+                self.tail = None;
+            }
+        }
+        drop(node_borrow);
+        self.len -= 1;
+
+        match Rc::try_unwrap(node) {
+            Ok(cell) => cell.into_inner().val,
+            Err(_) => unreachable!("LinkedList: на вузол залишились посилання"),
+        }
+    }
 
+    fn for_each_ref(&self, mut f: impl FnMut(&T)) {
+        let mut current = self.head.clone();
+        while let Some(node) = current {
+            let node = node.borrow();
+            f(&node.val);
+            current = node.next.clone();
+        }
+    }
+}
+
+impl<T: PartialEq> LinkedList<T> {
+    pub fn find_remove(&mut self, val: &T) -> Option<usize> {
+        let mut index = 0;
+        let mut current = self.head.clone();
+        while let Some(current_node) = current {
+            if current_node.borrow().val == *val {
+                self.unlink(current_node);
                 return Some(index);
             }
 
-            current = next;
+            current = current_node.borrow().next.clone();
             index += 1;
         }
 
         None
     }
 
-    pub fn index_remove(&mut self, index: usize) -> Option<T> {
-        let mut i = 0;
-        let mut current = self.head.clone();
-        while let Some(current_node) = current.as_ref() {
-            let next = current_node.borrow().next.clone();
-            if index == i {
-                let mut current_borrow = current_node.borrow_mut();
-
-                let prev = current_borrow.prev.take();
-                let next = current_borrow.next.take();
-                match (prev.as_ref().and_then(|w| w.upgrade()), next.as_ref()) {
-                    (Some(prev_strong), Some(next_strong)) => {
-                        prev_strong.borrow_mut().next = Some(next_strong.clone());
-                        next_strong.borrow_mut().prev = prev; // 'prev_link' - це Option<Weak<...>>
-                    }
+    pub fn contains(&self, val: &T) -> bool {
+        let mut found = false;
+        self.for_each_ref(|x| found |= x == val);
+        found
+    }
+}
 
-                    (None, Some(next_strong)) => {
-                        next_strong.borrow_mut().prev = None;
-                        self.head = Some(next_strong.clone());
-                    }
+impl<T: Clone> LinkedList<T> {
+    pub fn get(&self, index: usize) -> Option<T> {
+        self.node_at(index).map(|node| node.borrow().val.clone())
+    }
 
-                    (Some(prev_strong), None) => {
-                        prev_strong.borrow_mut().next = None;
-                        self.tail = Some(prev_strong.clone());
-                    }
+    pub fn front(&self) -> Option<T> {
+        self.head.as_ref().map(|node| node.borrow().val.clone())
+    }
 
-                    (None, None) => {
-                        self.head = None;
-                        self.tail = None;
+    pub fn back(&self) -> Option<T> {
+        self.tail.as_ref().map(|node| node.borrow().val.clone())
+    }
+
+    // Вузли живуть у RefCell, тому ітератор віддає копії значень.
+    pub fn iter(&self) -> Iter<'_, T> {
+        Iter {
+            front: self.head.clone(),
+            back: self.tail.clone(),
+            remaining: self.len,
+            list: PhantomData,
+        }
+    }
+}
+
+impl<T> Default for LinkedList<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Розбираємо ланцюжок в циклі, щоб довгий список не переповнив стек
+// рекурсивним drop.
+impl<T> Drop for LinkedList<T> {
+    fn drop(&mut self) {
+        self.tail.take();
+        let mut current = self.head.take();
+        while let Some(node) = current {
+            current = node.borrow_mut().next.take();
+        }
+    }
+}
+
+impl<T: Debug> Debug for LinkedList<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut list = f.debug_list();
+        self.for_each_ref(|x| {
+            list.entry(x);
+        });
+        list.finish()
+    }
+}
+
+impl<T: Clone> Clone for LinkedList<T> {
+    fn clone(&self) -> Self {
+        self.iter().collect()
+    }
+}
+
+impl<T: PartialEq> PartialEq for LinkedList<T> {
+    fn eq(&self, other: &Self) -> bool {
+        if self.len != other.len {
+            return false;
+        }
+
+        let mut a = self.head.clone();
+        let mut b = other.head.clone();
+        while let (Some(x), Some(y)) = (a, b) {
+            let (x, y) = (x.borrow(), y.borrow());
+            if x.val != y.val {
+                return false;
+            }
+            a = x.next.clone();
+            b = y.next.clone();
+        }
+        true
+    }
+}
+
+impl<T: Eq> Eq for LinkedList<T> {}
+
+// Позичає список, щоб його не змінили поки ітератор тримає вузли.
+pub struct Iter<'a, T> {
+    front: Link<T>,
+    back: Link<T>,
+    remaining: usize,
+    list: PhantomData<&'a LinkedList<T>>,
+}
+
+impl<T: Clone> Iterator for Iter<'_, T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        if self.remaining == 0 {
+            return None;
+        }
+        let node = self.front.take()?;
+        let node = node.borrow();
+        self.front = node.next.clone();
+        self.remaining -= 1;
+        Some(node.val.clone())
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<T: Clone> DoubleEndedIterator for Iter<'_, T> {
+    fn next_back(&mut self) -> Option<T> {
+        if self.remaining == 0 {
+            return None;
+        }
+        let node = self.back.take()?;
+        let node = node.borrow();
+        self.back = node.prev.as_ref().and_then(|w| w.upgrade());
+        self.remaining -= 1;
+        Some(node.val.clone())
+    }
+}
+
+impl<T: Clone> ExactSizeIterator for Iter<'_, T> {}
+
+// Ітератор тримає сильні посилання на вузли. Без Drop позика списку
+// закінчилася б на останньому виклику next, і список можна було б змінити,
+// поки вузли ще утримує ітератор - unlink тоді не зміг би забрати значення.
+impl<T> Drop for Iter<'_, T> {
+    fn drop(&mut self) {}
+}
+
+pub struct IntoIter<T>(LinkedList<T>);
+
+impl<T> Iterator for IntoIter<T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        self.0.pop_front()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.0.len, Some(self.0.len))
+    }
+}
+
+impl<T> DoubleEndedIterator for IntoIter<T> {
+    fn next_back(&mut self) -> Option<T> {
+        self.0.pop_back()
+    }
+}
+
+impl<T> ExactSizeIterator for IntoIter<T> {}
+
+impl<T> IntoIterator for LinkedList<T> {
+    type Item = T;
+    type IntoIter = IntoIter<T>;
+
+    fn into_iter(self) -> IntoIter<T> {
+        IntoIter(self)
+    }
+}
+
+impl<'a, T: Clone> IntoIterator for &'a LinkedList<T> {
+    type Item = T;
+    type IntoIter = Iter<'a, T>;
+
+    fn into_iter(self) -> Iter<'a, T> {
+        self.iter()
+    }
+}
+
+impl<T> Extend<T> for LinkedList<T> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        iter.into_iter().for_each(|x| self.push_back(x));
+    }
+}
+
+impl<T> FromIterator<T> for LinkedList<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut list = Self::new();
+        list.extend(iter);
+        list
+    }
+}
+
+pub trait Symbol: Copy + PartialEq + Debug {
+    const BYTES: usize;
+
+    // None, якщо індекс не вміщується в тип символа.
+    fn from_index(i: usize) -> Option<Self>;
+    fn index(self) -> usize;
+    fn read_le(bytes: &[u8]) -> Self;
+    fn write_le(self, out: &mut Vec<u8>);
+}
+
+macro_rules! impl_symbol {
+    ($($t:ty),*) => {$(
+        impl Symbol for $t {
+            const BYTES: usize = std::mem::size_of::<$t>();
+
+            fn from_index(i: usize) -> Option<Self> {
+                <$t>::try_from(i).ok()
+            }
+
+            fn index(self) -> usize {
+                self as usize
+            }
+
+            fn read_le(bytes: &[u8]) -> Self {
+                <$t>::from_le_bytes(bytes.try_into().unwrap())
+            }
+
+            fn write_le(self, out: &mut Vec<u8>) {
+                out.extend_from_slice(&self.to_le_bytes());
+            }
+        }
+    )*};
+}
+
+impl_symbol!(u8, u16, u32);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Alphabet {
+    // Усі символи 0..n відомі заздалегідь.
+    Fixed(usize),
+    // Алфавіт спочатку порожній: новий символ кодується рангом len() і самим символом.
+    Dynamic,
+}
+
+// Більші фіксовані алфавіти не будуються: на кожен символ припадає вузол списку.
+pub const MAX_ALPHABET: usize = 1 << 20;
+
+// Розмір фіксованого алфавіту: символи 0..size мають вміщуватися в S, а сам
+// розмір - не перевищувати MAX_ALPHABET.
+fn check_alphabet<S: Symbol>(alphabet: Alphabet) -> Result<()> {
+    match alphabet {
+        Alphabet::Fixed(size) if size as u64 > (1u64 << (8 * S::BYTES)).min(MAX_ALPHABET as u64) => {
+            Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!(
+                    "MTF: алфавіт з {size} символів не підходить для символів по {} байт \
+                     (щонайбільше {MAX_ALPHABET})",
+                    S::BYTES
+                ),
+            ))
+        }
+        _ => Ok(()),
+    }
+}
+
+fn init_alphabet<S: Symbol>(alphabet: Alphabet) -> LinkedList<S> {
+    let mut list: LinkedList<S> = LinkedList::new();
+    if let Alphabet::Fixed(size) = alphabet {
+        for i in 0..size {
+            list.push_front(S::from_index(i).expect("MTF: розмір алфавіту перевіряє check_alphabet"));
+        }
+    }
+    list
+}
+
+pub struct Mtf<S> {
+    alphabet: LinkedList<S>,
+    kind: Alphabet,
+}
+
+impl<S: Symbol> Mtf<S> {
+    pub fn new(kind: Alphabet) -> Result<Self> {
+        check_alphabet::<S>(kind)?;
+        Ok(Self {
+            alphabet: init_alphabet(kind),
+            kind,
+        })
+    }
+
+    // Дописує в out ранг символа (і сам символ, якщо він зустрівся вперше).
+    pub fn encode(&mut self, sym: S, out: &mut Vec<u32>) -> Result<()> {
+        match self.alphabet.find_remove(&sym) {
+            Some(index) => out.push(index as u32),
+            None if self.kind == Alphabet::Dynamic => {
+                out.push(self.alphabet.len() as u32);
+                out.push(sym.index() as u32);
+            }
+            None => {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    format!("MTF: символ {sym:?} поза алфавітом"),
+                ));
+            }
+        }
+        self.alphabet.push_front(sym);
+        Ok(())
+    }
+
+    // Бере з ranks стільки значень, скільки потрібно для одного символа.
+    pub fn decode(&mut self, ranks: &mut impl Iterator<Item = u32>) -> Result<Option<S>> {
+        let Some(rank) = ranks.next() else {
+            return Ok(None);
+        };
+        let rank = rank as usize;
+
+        let sym = if rank < self.alphabet.len() {
+            self.alphabet.index_remove(rank).unwrap()
+        } else if rank == self.alphabet.len() && self.kind == Alphabet::Dynamic {
+            match ranks.next() {
+                Some(index) => {
+                    let sym = S::from_index(index as usize).ok_or_else(|| {
+                        Error::new(
+                            ErrorKind::InvalidData,
+                            format!("MTF: символ {index} не вміщується в {} байт", S::BYTES),
+                        )
+                    })?;
+                    // кодер передає символ лише при першій появі
+                    if self.alphabet.contains(&sym) {
+                        return Err(Error::new(
+                            ErrorKind::InvalidData,
+                            format!("MTF: новий символ {sym:?} вже є в алфавіті"),
+                        ));
                     }
+                    sym
+                }
+                None => {
+                    return Err(Error::new(
+                        ErrorKind::UnexpectedEof,
+                        "MTF: після рангу нового символа немає самого символа",
+                    ));
                 }
-                drop(current_borrow);
-                return Some(current_node.borrow().val.clone());
             }
+        } else {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("MTF: ранг {rank} більший за розмір алфавіту"),
+            ));
+        };
+        self.alphabet.push_front(sym);
+        Ok(Some(sym))
+    }
+}
 
-            current = next;
-            i += 1;
+pub fn encode_symbols<S: Symbol>(input: &[S], alphabet: Alphabet) -> Result<Vec<u32>> {
+    let mut mtf = Mtf::new(alphabet)?;
+    let mut ranks = Vec::with_capacity(input.len());
+    input.iter().try_for_each(|&x| mtf.encode(x, &mut ranks))?;
+    Ok(ranks)
+}
+
+pub fn decode_symbols<S: Symbol>(ranks: &[u32], alphabet: Alphabet) -> Result<Vec<S>> {
+    let mut mtf = Mtf::new(alphabet)?;
+    let mut ranks = ranks.iter().copied();
+    let mut out = Vec::new();
+    while let Some(sym) = mtf.decode(&mut ranks)? {
+        out.push(sym);
+    }
+    Ok(out)
+}
+
+fn write_varint(mut x: u32, out: &mut Vec<u8>) {
+    while x >= 0x80 {
+        out.push((x as u8) | 0x80);
+        x >>= 7;
+    }
+    out.push(x as u8);
+}
+
+fn read_varint(reader: &mut impl Read) -> Result<Option<u32>> {
+    let mut x = 0u32;
+    let mut byte = [0];
+    for shift in (0..35).step_by(7) {
+        if reader.read(&mut byte)? == 0 {
+            if shift == 0 {
+                return Ok(None);
+            }
+            return Err(Error::new(
+                ErrorKind::UnexpectedEof,
+                "MTF: обірване число змінної довжини",
+            ));
         }
+        x |= ((byte[0] & 0x7f) as u32) << shift;
+        if byte[0] & 0x80 == 0 {
+            return Ok(Some(x));
+        }
+    }
 
-        None
+    Err(Error::new(
+        ErrorKind::InvalidData,
+        "MTF: число змінної довжини не вміщується в u32",
+    ))
+}
+
+fn for_each_symbol<S: Symbol>(
+    reader: &mut impl Read,
+    mut f: impl FnMut(S) -> Result<()>,
+) -> Result<()> {
+    let mut buf = vec![0; 8 * 1024 * S::BYTES];
+    let mut filled = 0;
+    loop {
+        let n = reader.read(&mut buf[filled..])?;
+        if n == 0 {
+            break;
+        }
+        filled += n;
+        let whole = filled - filled % S::BYTES;
+        buf[..whole]
+            .chunks_exact(S::BYTES)
+            .try_for_each(|chunk| f(S::read_le(chunk)))?;
+        buf.copy_within(whole..filled, 0);
+        filled -= whole;
+    }
+
+    if filled != 0 {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            "MTF: розмір файла не кратний ширині символу",
+        ));
     }
+    Ok(())
 }
 
-fn init_alphabet() -> LinkedList<u8> {
-    let mut alphabet: LinkedList<u8> = LinkedList::new();
-    for i in 0..=255 {
-        alphabet.push_front(i);
+// Заголовок: ширина символу в байтах, тип алфавіту (0 - фіксований, 1 - динамічний),
+// розмір алфавіту u32 le. Далі ранги у форматі LEB128.
+pub fn encode_wide<S: Symbol>(file_read: &str, file_write: &str, alphabet: Alphabet) -> Result<()> {
+    let mut mtf = Mtf::<S>::new(alphabet)?;
+    let mut reader = File::open(file_read)?;
+    let mut writer = BufWriter::new(File::create(file_write)?);
+
+    let (kind, size) = match alphabet {
+        Alphabet::Fixed(size) => (0u8, size as u32),
+        Alphabet::Dynamic => (1u8, 0),
+    };
+    writer.write_all(&[S::BYTES as u8, kind])?;
+    writer.write_all(&size.to_le_bytes())?;
+
+    let mut ranks = Vec::new();
+    let mut bytes = Vec::new();
+    for_each_symbol(&mut reader, |x: S| {
+        ranks.clear();
+        bytes.clear();
+        mtf.encode(x, &mut ranks)?;
+        ranks.iter().for_each(|&r| write_varint(r, &mut bytes));
+        writer.write_all(&bytes)
+    })?;
+    writer.flush()?;
+
+    Ok(())
+}
+
+pub fn decode_wide(file_read: &str, file_write: &str) -> Result<()> {
+    let mut reader = BufReader::new(File::open(file_read)?);
+    let mut header = [0; 6];
+    reader.read_exact(&mut header)?;
+    let size = u32::from_le_bytes(header[2..6].try_into().unwrap()) as usize;
+    let alphabet = match header[1] {
+        0 => Alphabet::Fixed(size),
+        1 => Alphabet::Dynamic,
+        _ => {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "MTF: невідомий тип алфавіту в заголовку",
+            ));
+        }
+    };
+
+    match header[0] {
+        1 => decode_wide_symbols::<u8>(reader, file_write, alphabet),
+        2 => decode_wide_symbols::<u16>(reader, file_write, alphabet),
+        4 => decode_wide_symbols::<u32>(reader, file_write, alphabet),
+        _ => Err(Error::new(
+            ErrorKind::InvalidData,
+            "MTF: непідтримувана ширина символу в заголовку",
+        )),
     }
-    alphabet
+}
+
+fn decode_wide_symbols<S: Symbol>(
+    mut reader: impl Read,
+    file_write: &str,
+    alphabet: Alphabet,
+) -> Result<()> {
+    let mut mtf = Mtf::<S>::new(alphabet)
+        .map_err(|e| Error::new(ErrorKind::InvalidData, e.to_string()))?;
+    let mut writer = BufWriter::new(File::create(file_write)?);
+
+    let mut err = None;
+    let mut ranks = std::iter::from_fn(|| match read_varint(&mut reader) {
+        Ok(rank) => rank,
+        Err(e) => {
+            err = Some(e);
+            None
+        }
+    });
+    let mut bytes = Vec::with_capacity(S::BYTES);
+    while let Some(sym) = mtf.decode(&mut ranks)? {
+        bytes.clear();
+        sym.write_le(&mut bytes);
+        writer.write_all(&bytes)?;
+    }
+    if let Some(e) = err {
+        return Err(e);
+    }
+    writer.flush()?;
+
+    Ok(())
 }
 
 pub fn encode(file_read: &str, file_write: &str) -> Result<()> {
     let mut reader = File::open(file_read)?;
     let mut writer = BufWriter::new(File::create(file_write)?);
 
-    let mut alphabet = init_alphabet();
+    let mut alphabet = init_alphabet::<u8>(Alphabet::Fixed(256));
     let mut buf = vec![0; 8 * 1024];
     while let Ok(n) = reader.read(&mut buf) {
         if n == 0 {
@@ -168,7 +740,7 @@ pub fn decode(file_read: &str, file_write: &str) -> Result<()> {
     let mut reader = File::open(file_read)?;
     let mut writer = BufWriter::new(File::create(file_write)?);
 
-    let mut alphabet = init_alphabet();
+    let mut alphabet = init_alphabet::<u8>(Alphabet::Fixed(256));
     let mut index = vec![0; 8 * 1024];
     while let Ok(n) = reader.read(&mut index) {
         if n == 0 {
@@ -185,3 +757,647 @@ pub fn decode(file_read: &str, file_write: &str) -> Result<()> {
 
     Ok(())
 }
+
+// Блок .bwt файла: BLOCK байт перетворення і байт первинного індексу.
+pub const BWT_BLOCK: usize = bwt::BLOCK + 1;
+// Початок блочного формату: "MTB" і версія. Звичайний формат заголовка не
+// має, тож декодер розрізняє їх за цими байтами.
+const BLOCKS_TAG: &[u8; 4] = b"MTB\x01";
+pub const MAX_BLOCK: usize = 64 << 20;
+// скільки байтів в одній порції блоків; порція містить щонайменше один блок
+const BATCH_BYTES: usize = 64 << 20;
+
+fn encode_block(block: &[u8]) -> Vec<u8> {
+    let mut alphabet = init_alphabet::<u8>(Alphabet::Fixed(256));
+    block
+        .iter()
+        .map(|x| {
+            let index = alphabet.find_remove(x).unwrap();
+            alphabet.push_front(*x);
+            index as u8
+        })
+        .collect()
+}
+
+fn decode_block(block: &[u8]) -> Vec<u8> {
+    let mut alphabet = init_alphabet::<u8>(Alphabet::Fixed(256));
+    block
+        .iter()
+        .map(|x| {
+            let byte = alphabet.index_remove(*x as usize).unwrap();
+            alphabet.push_front(byte);
+            byte
+        })
+        .collect()
+}
+
+fn read_block(reader: &mut impl Read, buf: &mut [u8]) -> Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        let n = reader.read(&mut buf[filled..])?;
+        if n == 0 {
+            break;
+        }
+        filled += n;
+    }
+    Ok(filled)
+}
+
+fn process_blocks(
+    reader: &mut impl Read,
+    writer: &mut impl Write,
+    block: usize,
+    f: fn(&[u8]) -> Vec<u8>,
+) -> Result<()> {
+    let mut buf = vec![0; block];
+    loop {
+        // 1) Збираємо порцію блоків
+        let mut batch: Vec<Vec<u8>> = Vec::new();
+        let mut batch_bytes = 0;
+        while batch.is_empty() || batch_bytes + block <= BATCH_BYTES {
+            let n = read_block(reader, &mut buf)?;
+            if n == 0 {
+                break;
+            }
+            batch.push(buf[..n].to_vec());
+            batch_bytes += n;
+        }
+
+        if batch.is_empty() {
+            break;
+        }
+
+        // 2) Паралельна обробка: кожен блок починає з початкового алфавіту
+        let results: Vec<Vec<u8>> = batch.par_iter().map(|b| f(b)).collect();
+
+        // 3) Послідовний запис
+        for res in results {
+            writer.write_all(&res)?;
+        }
+    }
+    writer.flush()
+}
+
+// Заголовок: BLOCKS_TAG, розмір блока u32 le. Щоб межі збігались з блоками BWT,
+// варто брати BWT_BLOCK.
+pub fn encode_blocks(file_read: &str, file_write: &str, block: usize) -> Result<()> {
+    check_block(block)?;
+    let mut reader = File::open(file_read)?;
+    let mut writer = BufWriter::new(File::create(file_write)?);
+
+    writer.write_all(BLOCKS_TAG)?;
+    writer.write_all(&(block as u32).to_le_bytes())?;
+    process_blocks(&mut reader, &mut writer, block, encode_block)
+}
+
+// Розмір блока задається так само, як при кодуванні, і має збігатися із
+// заголовком: звичайний потік MTF заголовка не має, тож формат не вгадується.
+pub fn decode_blocks(file_read: &str, file_write: &str, block: usize) -> Result<()> {
+    check_block(block)?;
+    let mut reader = File::open(file_read)?;
+    let mut writer = BufWriter::new(File::create(file_write)?);
+
+    let mut header = [0; 8];
+    reader.read_exact(&mut header)?;
+    if &header[..4] != BLOCKS_TAG {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            "MTF: невідомий формат або версія блоків",
+        ));
+    }
+    let stored = u32::from_le_bytes(header[4..].try_into().unwrap()) as usize;
+    if stored != block {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            format!("MTF: потік закодовано блоками по {stored} байт, а задано {block}"),
+        ));
+    }
+    process_blocks(&mut reader, &mut writer, block, decode_block)
+}
+
+fn check_block(block: usize) -> Result<()> {
+    if block == 0 || block > MAX_BLOCK {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!("MTF: розмір блока має бути від 1 до {MAX_BLOCK} байт"),
+        ));
+    }
+    Ok(())
+}
+
+fn entropy(hist: &[u64], total: u64) -> f64 {
+    if total == 0 {
+        return 0.0;
+    }
+    hist.iter()
+        .filter(|&&x| x != 0)
+        .map(|&x| {
+            let p = x as f64 / total as f64;
+            p * (1.0 / p).log2()
+        })
+        .sum()
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Stats {
+    pub block: usize,
+    pub total: u64,
+    // histogram[r] - скільки разів зустрівся ранг r
+    pub histogram: Vec<u64>,
+    // довжина серії нулів -> кількість таких серій
+    pub zero_runs: BTreeMap<usize, u64>,
+    // ентропія нульового порядку в бітах на символ для кожного блока
+    pub block_entropy: Vec<f64>,
+    block_hist: Vec<u64>,
+    block_len: usize,
+    run: usize,
+}
+
+impl Stats {
+    pub fn new(block: usize) -> Self {
+        assert!(block > 0, "MTF: розмір блока статистики має бути додатним");
+        Self {
+            block,
+            ..Default::default()
+        }
+    }
+
+    pub fn push(&mut self, rank: u32) {
+        let rank = rank as usize;
+        if rank >= self.histogram.len() {
+            self.histogram.resize(rank + 1, 0);
+        }
+        if rank >= self.block_hist.len() {
+            self.block_hist.resize(rank + 1, 0);
+        }
+        self.histogram[rank] += 1;
+        self.block_hist[rank] += 1;
+        self.total += 1;
+
+        if rank == 0 {
+            self.run += 1;
+        } else {
+            self.end_run();
+        }
+
+        self.block_len += 1;
+        if self.block_len == self.block {
+            self.end_block();
+        }
+    }
+
+    // Закриває незавершені серію і блок. Викликати після останнього push.
+    pub fn finish(&mut self) {
+        self.end_run();
+        if self.block_len != 0 {
+            self.end_block();
+        }
+    }
+
+    fn end_run(&mut self) {
+        if self.run != 0 {
+            *self.zero_runs.entry(self.run).or_insert(0) += 1;
+            self.run = 0;
+        }
+    }
+
+    fn end_block(&mut self) {
+        self.block_entropy
+            .push(entropy(&self.block_hist, self.block_len as u64));
+        self.block_hist.iter_mut().for_each(|x| *x = 0);
+        self.block_len = 0;
+    }
+
+    pub fn zero_fraction(&self) -> f64 {
+        match self.total {
+            0 => 0.0,
+            total => self.histogram.first().copied().unwrap_or(0) as f64 / total as f64,
+        }
+    }
+
+    pub fn entropy(&self) -> f64 {
+        entropy(&self.histogram, self.total)
+    }
+
+    pub fn to_json(&self) -> String {
+        let histogram = self
+            .histogram
+            .iter()
+            .map(|x| x.to_string())
+            .collect::<Vec<_>>()
+            .join(",");
+        let zero_runs = self
+            .zero_runs
+            .iter()
+            .map(|(len, count)| format!("\"{len}\":{count}"))
+            .collect::<Vec<_>>()
+            .join(",");
+        let block_entropy = self
+            .block_entropy
+            .iter()
+            .map(|x| format!("{x:.6}"))
+            .collect::<Vec<_>>()
+            .join(",");
+
+        format!(
+            "{{\"total\":{},\"block\":{},\"zero_fraction\":{:.6},\"entropy\":{:.6},\"histogram\":[{}],\"zero_runs\":{{{}}},\"block_entropy\":[{}]}}",
+            self.total,
+            self.block,
+            self.zero_fraction(),
+            self.entropy(),
+            histogram,
+            zero_runs,
+            block_entropy
+        )
+    }
+}
+
+impl Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "symbols: {}", self.total)?;
+        writeln!(f, "zero fraction: {:.4}", self.zero_fraction())?;
+        writeln!(f, "entropy: {:.4} bits/symbol", self.entropy())?;
+
+        writeln!(f, "rank histogram:")?;
+        for (rank, &count) in self.histogram.iter().enumerate() {
+            if count != 0 {
+                writeln!(
+                    f,
+                    "  {rank:>5}: {count:>12} ({:.4})",
+                    count as f64 / self.total as f64
+                )?;
+            }
+        }
+
+        writeln!(f, "zero runs:")?;
+        for (len, count) in &self.zero_runs {
+            writeln!(f, "  {len:>5}: {count:>12}")?;
+        }
+
+        writeln!(f, "entropy per block of {} symbols:", self.block)?;
+        for (i, h) in self.block_entropy.iter().enumerate() {
+            writeln!(f, "  {i:>5}: {h:.4}")?;
+        }
+        Ok(())
+    }
+}
+
+// Проганяє MTF по файлу і збирає статистику рангів. Якщо reset, алфавіт
+// скидається на початку кожного блока, як у encode_blocks.
+pub fn collect_stats(mut reader: impl Read, block: usize, reset: bool) -> Result<Stats> {
+    let mut stats = Stats::new(block);
+
+    let mut alphabet = init_alphabet::<u8>(Alphabet::Fixed(256));
+    let mut buf = vec![0; 8 * 1024];
+    let mut pos = 0;
+    loop {
+        let n = reader.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        buf.iter().take(n).for_each(|x| {
+            if reset && pos % block == 0 {
+                alphabet = init_alphabet(Alphabet::Fixed(256));
+            }
+            let index = alphabet.find_remove(x).unwrap();
+            alphabet.push_front(*x);
+            stats.push(index as u32);
+            pos += 1;
+        });
+    }
+    stats.finish();
+
+    Ok(stats)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::VecDeque;
+
+    // Порівнює список із VecDeque в обидва боки: зворотний обхід іде по
+    // слабких посиланнях prev, тож перевіряє і їх.
+    fn check(list: &LinkedList<i32>, model: &VecDeque<i32>) {
+        assert_eq!(list.len(), model.len());
+        assert_eq!(list.is_empty(), model.is_empty());
+        assert_eq!(list.front(), model.front().copied());
+        assert_eq!(list.back(), model.back().copied());
+        assert!(list.iter().eq(model.iter().copied()));
+        assert!(list.iter().rev().eq(model.iter().rev().copied()));
+        assert_eq!(list.iter().len(), model.len());
+    }
+
+    #[test]
+    fn push_pop_both_ends() {
+        let mut list = LinkedList::new();
+        let mut model = VecDeque::new();
+        check(&list, &model);
+        assert_eq!(list.pop_front(), None);
+        assert_eq!(list.pop_back(), None);
+
+        for i in 0..10 {
+            if i % 3 == 0 {
+                list.push_front(i);
+                model.push_front(i);
+            } else {
+                list.push_back(i);
+                model.push_back(i);
+            }
+            check(&list, &model);
+        }
+        while !model.is_empty() {
+            if model.len() % 2 == 0 {
+                assert_eq!(list.pop_front(), model.pop_front());
+            } else {
+                assert_eq!(list.pop_back(), model.pop_back());
+            }
+            check(&list, &model);
+        }
+        assert_eq!(list.pop_front(), None);
+
+        // порожній після спустошення список знову працює з обох кінців
+        list.push_back(1);
+        list.push_front(0);
+        check(&list, &VecDeque::from([0, 1]));
+    }
+
+    #[test]
+    fn remove_head_tail_middle() {
+        let items: Vec<i32> = (0..7).collect();
+        for index in [0, 3, 6] {
+            let mut list: LinkedList<i32> = items.iter().copied().collect();
+            let mut model: VecDeque<i32> = items.iter().copied().collect();
+            assert_eq!(list.index_remove(index), model.remove(index));
+            check(&list, &model);
+
+            let mut list: LinkedList<i32> = items.iter().copied().collect();
+            assert_eq!(list.find_remove(&(index as i32)), Some(index));
+            check(&list, &model);
+        }
+        let mut list: LinkedList<i32> = items.iter().copied().collect();
+        assert_eq!(list.index_remove(7), None);
+        assert_eq!(list.find_remove(&42), None);
+        check(&list, &items.iter().copied().collect());
+
+        // єдиний елемент - водночас голова і хвіст
+        let mut list: LinkedList<i32> = [5].into_iter().collect();
+        assert_eq!(list.index_remove(0), Some(5));
+        check(&list, &VecDeque::new());
+        list.push_back(6);
+        assert_eq!(list.find_remove(&6), Some(0));
+        check(&list, &VecDeque::new());
+    }
+
+    #[test]
+    fn insert_and_get() {
+        let mut list = LinkedList::new();
+        let mut model = VecDeque::new();
+        for (i, index) in [0, 1, 1, 0, 4, 2].into_iter().enumerate() {
+            list.insert_at(index, i as i32);
+            model.insert(index, i as i32);
+            check(&list, &model);
+        }
+        for (index, &x) in model.iter().enumerate() {
+            assert_eq!(list.get(index), Some(x));
+            assert!(list.contains(&x));
+        }
+        assert_eq!(list.get(model.len()), None);
+        assert!(!list.contains(&42));
+    }
+
+    #[test]
+    fn iterators() {
+        let list: LinkedList<i32> = (0..5).collect();
+        let mut iter = list.iter();
+        assert_eq!(iter.next(), Some(0));
+        assert_eq!(iter.next_back(), Some(4));
+        assert_eq!(iter.len(), 3);
+        assert_eq!(iter.collect::<Vec<_>>(), [1, 2, 3]);
+        // ітератори з двох боків зустрічаються без повторів
+        let mut iter = list.iter();
+        let mut seen = Vec::new();
+        while let (Some(a), b) = (iter.next(), iter.next_back()) {
+            seen.push(a);
+            seen.extend(b);
+        }
+        drop(iter);
+        seen.sort();
+        assert_eq!(seen, [0, 1, 2, 3, 4]);
+        assert_eq!((&list).into_iter().sum::<i32>(), 10);
+
+        let mut into = list.into_iter();
+        assert_eq!(into.len(), 5);
+        assert_eq!(into.next_back(), Some(4));
+        assert_eq!(into.next(), Some(0));
+        assert_eq!(into.collect::<Vec<_>>(), [1, 2, 3]);
+    }
+
+    #[test]
+    fn extend_clone_eq() {
+        let mut list: LinkedList<i32> = (0..3).collect();
+        list.extend(3..6);
+        check(&list, &(0..6).collect());
+
+        let copy = list.clone();
+        assert_eq!(copy, list);
+        assert_eq!(format!("{copy:?}"), "[0, 1, 2, 3, 4, 5]");
+        // копія не ділить вузлів з оригіналом
+        list.pop_front();
+        list.push_back(6);
+        check(&copy, &(0..6).collect());
+        assert_ne!(copy, list);
+        assert_ne!(copy, (0..5).collect());
+        assert_eq!(LinkedList::<i32>::new(), LinkedList::default());
+
+        list.clear();
+        check(&list, &VecDeque::new());
+    }
+
+    #[test]
+    fn drop_long_list() {
+        // рекурсивний drop такого ланцюжка переповнив би стек
+        let marker = Rc::new(());
+        let list: LinkedList<Rc<()>> = (0..1_000_000).map(|_| marker.clone()).collect();
+        assert_eq!(Rc::strong_count(&marker), 1_000_001);
+        drop(list);
+        assert_eq!(Rc::strong_count(&marker), 1);
+    }
+
+    #[test]
+    fn symbol_alphabets() {
+        let input: Vec<u16> = vec![300, 7, 300, 65535, 7];
+        for alphabet in [Alphabet::Fixed(65536), Alphabet::Dynamic] {
+            let ranks = encode_symbols(&input, alphabet).unwrap();
+            assert_eq!(decode_symbols::<u16>(&ranks, alphabet).unwrap(), input);
+        }
+
+        // алфавіт більший, ніж вміщує тип символа
+        assert_eq!(u8::from_index(256), None);
+        assert_eq!(u16::from_index(65535), Some(65535));
+        let err = encode_symbols::<u8>(&[1], Alphabet::Fixed(257)).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidInput);
+        assert!(encode_symbols::<u32>(&[1], Alphabet::Fixed(1 << 32)).is_err());
+
+        // новий символ, що не вміщується в тип або вже є в алфавіті
+        for ranks in [&[0, 256][..], &[0, 5, 1, 5][..]] {
+            let err = decode_symbols::<u8>(ranks, Alphabet::Dynamic).unwrap_err();
+            assert_eq!(err.kind(), ErrorKind::InvalidData, "{ranks:?}");
+        }
+    }
+
+    fn temp(name: &str) -> String {
+        let path = std::env::temp_dir().join(format!("mtf-test-{}-{name}", std::process::id()));
+        path.to_str().unwrap().to_string()
+    }
+
+    #[test]
+    fn blocks() {
+        let (input, packed, output) = (temp("blocks"), temp("blocks.mtb"), temp("blocks.out"));
+        let data: Vec<u8> = (0..3000u32).map(|i| (i * i % 251) as u8).collect();
+        for (data, block) in [(&data[..], 1), (&data[..], 7), (&data[..], BWT_BLOCK), (&[][..], 5)] {
+            std::fs::write(&input, data).unwrap();
+            encode_blocks(&input, &packed, block).unwrap();
+            decode_blocks(&packed, &output, block).unwrap();
+            assert!(std::fs::read(&output).unwrap() == data, "блок {block}");
+        }
+
+        // розмір блока обмежений і в кодері, і в заголовку
+        for block in [0, MAX_BLOCK + 1] {
+            let err = encode_blocks(&input, &packed, block).unwrap_err();
+            assert_eq!(err.kind(), ErrorKind::InvalidInput);
+        }
+        let mut header = BLOCKS_TAG.to_vec();
+        header.extend(u32::MAX.to_le_bytes());
+        std::fs::write(&packed, header).unwrap();
+        let err = decode_blocks(&packed, &output, MAX_BLOCK).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+
+        // звичайний потік, що починається з BLOCKS_TAG, декодується звичайним
+        // decode, а decode_blocks вимагає збігу розміру блока
+        let plain: Vec<u8> = BLOCKS_TAG.iter().chain(&[5, 0, 0, 0, 9, 9]).copied().collect();
+        std::fs::write(&packed, &plain).unwrap();
+        decode(&packed, &input).unwrap();
+        encode(&input, &packed).unwrap();
+        assert!(std::fs::read(&packed).unwrap() == plain);
+        decode(&packed, &output).unwrap();
+        assert!(std::fs::read(&output).unwrap() == std::fs::read(&input).unwrap());
+        assert_eq!(decode_blocks(&packed, &output, 7).unwrap_err().kind(), ErrorKind::InvalidData);
+
+        for path in [input, packed, output] {
+            std::fs::remove_file(path).unwrap();
+        }
+    }
+
+    fn wide_round_trip<S: Symbol>(name: &str, symbols: &[S], alphabet: Alphabet) {
+        let (input, packed, output) = (temp(name), temp(&format!("{name}.mtw")), temp(&format!("{name}.out")));
+        let mut data = Vec::new();
+        symbols.iter().for_each(|&x| x.write_le(&mut data));
+        std::fs::write(&input, &data).unwrap();
+        encode_wide::<S>(&input, &packed, alphabet).unwrap();
+        decode_wide(&packed, &output).unwrap();
+        assert!(std::fs::read(&output).unwrap() == data, "{name}: {alphabet:?}");
+        for path in [input, packed, output] {
+            std::fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn wide_files() {
+        let mut x = 11u32;
+        let mut next = move || {
+            x = x.wrapping_mul(1103515245).wrapping_add(12345);
+            x >> 8
+        };
+        let raw: Vec<u32> = (0..5000).map(|_| next() % 300 * (next() % 3)).collect();
+        for alphabet in [Alphabet::Fixed(256), Alphabet::Dynamic] {
+            let bytes: Vec<u8> = raw.iter().map(|&x| x as u8).collect();
+            wide_round_trip("wide8", &bytes, alphabet);
+            wide_round_trip::<u8>("wide8-empty", &[], alphabet);
+        }
+        for alphabet in [Alphabet::Fixed(65536), Alphabet::Dynamic] {
+            let words: Vec<u16> = raw.iter().map(|&x| (x * 211) as u16).chain([65535]).collect();
+            wide_round_trip("wide16", &words, alphabet);
+        }
+        for alphabet in [Alphabet::Fixed(MAX_ALPHABET), Alphabet::Dynamic] {
+            // великі символи стоять на початку фіксованого алфавіту, тож пошук короткий
+            let wide: Vec<u32> = raw.iter().map(|&x| MAX_ALPHABET as u32 - 1 - x * 3491 % 9973).collect();
+            wide_round_trip("wide32", &wide, alphabet);
+        }
+        wide_round_trip("wide32-big", &[u32::MAX, 0, u32::MAX], Alphabet::Dynamic);
+
+        // алфавіт більший за MAX_ALPHABET не будується ні кодером, ні декодером
+        let (packed, output) = (temp("wide-bad.mtw"), temp("wide-bad.out"));
+        let err = encode_wide::<u32>(&packed, &output, Alphabet::Fixed(MAX_ALPHABET + 1)).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidInput);
+        let headers: [(&[u8], ErrorKind); 6] = [
+            (&[4, 0, 0xff, 0xff, 0xff, 0xff], ErrorKind::InvalidData),
+            (&[4, 0, 1, 0, 0x10, 0], ErrorKind::InvalidData),
+            (&[1, 0, 1, 1, 0, 0], ErrorKind::InvalidData),
+            (&[3, 1, 0, 0, 0, 0], ErrorKind::InvalidData),
+            (&[1, 2, 0, 0, 0, 0], ErrorKind::InvalidData),
+            (&[1, 0, 0], ErrorKind::UnexpectedEof),
+        ];
+        for (header, kind) in headers {
+            std::fs::write(&packed, header).unwrap();
+            assert_eq!(decode_wide(&packed, &output).unwrap_err().kind(), kind, "{header:?}");
+        }
+        // ранг поза фіксованим алфавітом
+        std::fs::write(&packed, [1, 0, 4, 0, 0, 0, 2, 9]).unwrap();
+        assert_eq!(decode_wide(&packed, &output).unwrap_err().kind(), ErrorKind::InvalidData);
+        for path in [packed, output] {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+
+    fn stats_of(data: &[u8], block: usize, reset: bool) -> Stats {
+        collect_stats(data, block, reset).unwrap()
+    }
+
+    #[test]
+    fn rank_stats() {
+        // алфавіт починається з 255: ранги 0, 0, 1, 0, 1
+        let data = [255, 255, 254, 254, 255];
+        let stats = stats_of(&data, 2, false);
+        assert_eq!((stats.total, &stats.histogram[..]), (5, &[3, 2][..]));
+        assert_eq!(stats.zero_runs, BTreeMap::from([(1, 1), (2, 1)]));
+        assert_eq!(stats.block_entropy, [0.0, 1.0, 0.0]);
+        assert_eq!(
+            stats.to_json(),
+            "{\"total\":5,\"block\":2,\"zero_fraction\":0.600000,\"entropy\":0.970951,\
+             \"histogram\":[3,2],\"zero_runs\":{\"1\":1,\"2\":1},\
+             \"block_entropy\":[0.000000,1.000000,0.000000]}"
+        );
+
+        // reset: кожен блок починає з початкового алфавіту, тож ранги 0, 0, 1, 0, 0
+        let stats = stats_of(&data, 2, true);
+        assert_eq!(&stats.histogram[..], [4, 1]);
+        assert_eq!(stats.zero_runs, BTreeMap::from([(2, 2)]));
+        assert_eq!(stats.block_entropy, [0.0, 1.0, 0.0]);
+
+        // серія нулів не обривається на межі блока
+        let stats = stats_of(&[255; 5], 2, true);
+        assert_eq!(stats.zero_runs, BTreeMap::from([(5, 1)]));
+        assert_eq!(stats.block_entropy.len(), 3);
+
+        assert_eq!(
+            stats_of(&[], 4, false).to_json(),
+            "{\"total\":0,\"block\":4,\"zero_fraction\":0.000000,\"entropy\":0.000000,\
+             \"histogram\":[],\"zero_runs\":{},\"block_entropy\":[]}"
+        );
+
+        // ті самі ранги, що й у encode
+        let data: Vec<u8> = (0..20_000u32).map(|i| (i * i % 97 + i / 1000) as u8).collect();
+        let (input, packed) = (temp("stats"), temp("stats.mtf"));
+        std::fs::write(&input, &data).unwrap();
+        encode(&input, &packed).unwrap();
+        let ranks = std::fs::read(&packed).unwrap();
+        let mut histogram = vec![0u64; 256];
+        ranks.iter().for_each(|&r| histogram[r as usize] += 1);
+        let stats = stats_of(&data, BWT_BLOCK, false);
+        assert_eq!(stats.histogram, histogram[..stats.histogram.len()]);
+        assert_eq!(stats.total, data.len() as u64);
+        for path in [input, packed] {
+            std::fs::remove_file(path).unwrap();
+        }
+    }
+}
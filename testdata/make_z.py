@@ -0,0 +1,136 @@
+#!/usr/bin/env python3
+# Генерує фікстури .Z для тестів lzw.rs. Кодер - порт compress()/output()/
+# cl_block() з compress 4.0, незалежний від encode_z; кожен файл додатково
+# перевіряється через gzip -d (його unlzw - окрема реалізація декодера).
+import subprocess
+import sys
+
+CHECK_GAP = 10000
+CLEAR = 256
+FIRST = 257
+
+
+def sample():
+    # ~20 КБ псевдотексту, потім ~50 КБ випадкових байтів: словник -b12
+    # заповнюється на тексті, а на шумі стиснення падає і йде CLEAR.
+    x = 2024
+
+    def rnd():
+        nonlocal x
+        x = (x * 1103515245 + 12345) & 0xFFFFFFFF
+        return x >> 16
+
+    words = [b"the", b"of", b"and", b"lzw", b"code", b"table", b"width",
+             b"clear", b"stream", b"byte", b"word", b"compress", b"group"]
+    text = bytearray()
+    while len(text) < 20000:
+        text += words[rnd() % len(words)]
+        text += b"\n" if rnd() % 9 == 0 else b" "
+    noise = bytes(rnd() & 0xFF for _ in range(50000))
+    return bytes(text) + noise
+
+
+class Compress:
+    def __init__(self, maxbits):
+        self.out = bytearray([0x1F, 0x9D, maxbits | 0x80])
+        self.maxbits = maxbits
+        self.maxmaxcode = 1 << maxbits
+        self.n_bits = 9
+        self.maxcode = (1 << 9) - 1
+        self.free_ent = FIRST
+        self.clear_flg = False
+        self.bytes_out = 3
+        # коди поточної групи (buf і offset в оригіналі)
+        self.group = []
+
+    def flush(self, nbits):
+        acc = 0
+        for i, code in enumerate(self.group):
+            acc |= code << (i * self.n_bits)
+        self.out += acc.to_bytes((nbits + 7) // 8, "little")
+        self.group = []
+
+    def output(self, code):
+        if code is None:
+            # кінець: дописати неповну групу до байта
+            if self.group:
+                self.flush(len(self.group) * self.n_bits)
+            return
+        self.group.append(code)
+        if len(self.group) == 8:
+            self.flush(8 * self.n_bits)
+            self.bytes_out += self.n_bits
+        if self.free_ent > self.maxcode or self.clear_flg:
+            # зміна ширини: неповна група пишеться цілими n_bits байтами
+            if self.group:
+                self.flush(8 * self.n_bits)
+                self.bytes_out += self.n_bits
+            if self.clear_flg:
+                self.n_bits = 9
+                self.maxcode = (1 << 9) - 1
+                self.clear_flg = False
+            else:
+                self.n_bits += 1
+                if self.n_bits == self.maxbits:
+                    self.maxcode = self.maxmaxcode
+                else:
+                    self.maxcode = (1 << self.n_bits) - 1
+
+
+def compress(data, maxbits):
+    z = Compress(maxbits)
+    table = {}
+    in_count = 1
+    checkpoint = CHECK_GAP
+    ratio = 0
+    ent = data[0]
+    for c in data[1:]:
+        in_count += 1
+        code = table.get((ent, c))
+        if code is not None:
+            ent = code
+            continue
+        z.output(ent)
+        prefix, ent = ent, c
+        if z.free_ent < z.maxmaxcode:
+            table[(prefix, c)] = z.free_ent
+            z.free_ent += 1
+        elif in_count >= checkpoint:
+            # cl_block()
+            checkpoint = in_count + CHECK_GAP
+            if in_count > 0x007FFFFF:
+                rat = z.bytes_out >> 8
+                rat = 0x7FFFFFFF if rat == 0 else in_count // rat
+            else:
+                rat = (in_count << 8) // z.bytes_out
+            if rat > ratio:
+                ratio = rat
+            else:
+                ratio = 0
+                table.clear()
+                z.free_ent = FIRST
+                z.clear_flg = True
+                z.output(CLEAR)
+    z.output(ent)
+    z.output(None)
+    return bytes(z.out)
+
+
+def main():
+    data = sample()
+    with open("sample.bin", "wb") as f:
+        f.write(data)
+    for bits in (9, 12, 16):
+        name = f"sample.b{bits}.Z"
+        packed = compress(data, bits)
+        with open(name, "wb") as f:
+            f.write(packed)
+        unpacked = subprocess.run(["gzip", "-dc", name], capture_output=True,
+                                  check=True).stdout
+        if unpacked != data:
+            sys.exit(f"{name}: gzip -d дав інші байти")
+        print(name, len(packed))
+
+
+if __name__ == "__main__":
+    main()